@@ -0,0 +1,14 @@
+use std::env;
+
+fn main() {
+    // The linker script aligns sections and sizes guard pages to the translation granule, see
+    // `PAGE_SIZE` in memory.x.
+    let page_size = if env::var_os("CARGO_FEATURE_GRANULE_4K").is_some() {
+        4 * 1024
+    } else {
+        64 * 1024
+    };
+
+    println!("cargo:rustc-link-arg=--defsym=__page_size={:#x}", page_size);
+    println!("cargo:rerun-if-changed=build.rs");
+}
@@ -1,33 +1,92 @@
 /// The board's physical memory map.
 pub mod mmu;
 
-use core::cell::UnsafeCell;
+use core::{cell::UnsafeCell, ops::RangeInclusive};
+
+use ros_sys::memory::phys_to_virt;
 
 // Symbols from the linker script.
 extern "Rust" {
+    static __boot_reserved_start: UnsafeCell<()>;
+
+    static __boot_core_stack_guard_page_start: UnsafeCell<()>;
+    static __boot_core_stack_guard_page_end_exclusive: UnsafeCell<()>;
+
+    static __boot_core_stack_start: UnsafeCell<()>;
+    static __boot_core_stack_end_exclusive: UnsafeCell<()>;
+
     static __code_start: UnsafeCell<()>;
     static __code_end_exclusive: UnsafeCell<()>;
+
+    static __data_start: UnsafeCell<()>;
+    static __data_end_exclusive: UnsafeCell<()>;
+
+    static __heap_start: UnsafeCell<()>;
+    static __heap_end_exclusive: UnsafeCell<()>;
 }
 
 pub(super) mod map {
     /// The inclusive end address of the memory map.
     pub const END_INCLUSIVE: usize = 0xffff_ffff;
 
+    /// The ARM-visible DRAM below the VideoCore split, present on every RPi4 variant.
+    pub const DRAM_START: usize = 0x0000_0000;
+    pub const DRAM_END_INCLUSIVE: usize = 0x3b3f_ffff;
+
+    pub const SYSTEM_TIMER_OFFSET: usize = 0x0000_3000;
     pub const GPIO_OFFSET: usize = 0x0020_0000;
     pub const UART_OFFSET: usize = 0x0020_1000;
+    pub const PM_OFFSET: usize = 0x0010_0000;
 
     pub mod mmio {
         use super::*;
 
         pub const BASE: usize = 0xfe00_0000;
+        pub const SYSTEM_TIMER_BASE: usize = BASE + SYSTEM_TIMER_OFFSET;
         pub const GPIO_BASE: usize = BASE + GPIO_OFFSET;
         pub const UART_BASE: usize = BASE + UART_OFFSET;
+        pub const PM_BASE: usize = BASE + PM_OFFSET;
         pub const GICD_BASE: usize = 0xff84_1000;
         pub const GICC_BASE: usize = 0xff84_2000;
         pub const END_INCLUSIVE: usize = 0xff84_ffff;
     }
 }
 
+/// Start address of the memory below the kernel image.
+/// # Safety
+#[inline(always)]
+fn boot_reserved_start() -> usize {
+    unsafe { __boot_reserved_start.get() as usize }
+}
+
+/// Start page address of the boot core stack guard page.
+/// # Safety
+#[inline(always)]
+fn boot_core_stack_guard_page_start() -> usize {
+    unsafe { __boot_core_stack_guard_page_start.get() as usize }
+}
+
+/// Exclusive end page address of the boot core stack guard page.
+/// # Safety
+#[inline(always)]
+fn boot_core_stack_guard_page_end_exclusive() -> usize {
+    unsafe { __boot_core_stack_guard_page_end_exclusive.get() as usize }
+}
+
+/// Start address of the boot core stack.
+/// # Safety
+#[inline(always)]
+fn boot_core_stack_start() -> usize {
+    unsafe { __boot_core_stack_start.get() as usize }
+}
+
+/// Exclusive end address of the boot core stack.
+/// # Safety
+#[inline(always)]
+fn boot_core_stack_end_exclusive() -> usize {
+    unsafe { __boot_core_stack_end_exclusive.get() as usize }
+}
+
 /// Start page address of the code segment.
 /// # Safety
 #[inline(always)]
@@ -41,3 +100,75 @@ fn code_start() -> usize {
 fn code_end_exclusive() -> usize {
     unsafe { __code_end_exclusive.get() as usize }
 }
+
+/// Start page address of the data segment.
+/// # Safety
+#[inline(always)]
+fn data_start() -> usize {
+    unsafe { __data_start.get() as usize }
+}
+
+/// Exclusive end page address of the data segment, including bss.
+/// # Safety
+#[inline(always)]
+fn data_end_exclusive() -> usize {
+    unsafe { __data_end_exclusive.get() as usize }
+}
+
+/// Start page address of the kernel heap.
+/// # Safety
+#[inline(always)]
+fn heap_start() -> usize {
+    unsafe { __heap_start.get() as usize }
+}
+
+/// Exclusive end page address of the kernel heap.
+/// # Safety
+#[inline(always)]
+fn heap_end_exclusive() -> usize {
+    unsafe { __heap_end_exclusive.get() as usize }
+}
+
+/// The region reserved for the kernel heap.
+pub fn heap_region() -> RangeInclusive<usize> {
+    RangeInclusive::new(heap_start(), heap_end_exclusive() - 1)
+}
+
+/// The DRAM above the kernel heap, which the frame allocator hands out.
+pub fn free_dram_region() -> RangeInclusive<usize> {
+    RangeInclusive::new(heap_end_exclusive(), phys_to_virt(map::DRAM_END_INCLUSIVE))
+}
+
+/// The DRAM usable by the kernel.
+pub fn dram_region() -> RangeInclusive<usize> {
+    RangeInclusive::new(map::DRAM_START, map::DRAM_END_INCLUSIVE)
+}
+
+/// The region below the kernel image: the firmware's armstub, the boot core stack and its guard
+/// page.
+pub fn boot_reserved_region() -> RangeInclusive<usize> {
+    RangeInclusive::new(boot_reserved_start(), boot_core_stack_end_exclusive() - 1)
+}
+
+/// The unmapped page below the boot core stack.
+pub fn boot_core_stack_guard_page_region() -> RangeInclusive<usize> {
+    RangeInclusive::new(
+        boot_core_stack_guard_page_start(),
+        boot_core_stack_guard_page_end_exclusive() - 1,
+    )
+}
+
+/// The region used by the boot core stack.
+pub fn boot_core_stack_region() -> RangeInclusive<usize> {
+    RangeInclusive::new(boot_core_stack_start(), boot_core_stack_end_exclusive() - 1)
+}
+
+/// The region used by the kernel data and bss.
+pub fn data_region() -> RangeInclusive<usize> {
+    RangeInclusive::new(data_start(), data_end_exclusive() - 1)
+}
+
+/// The region used by the kernel image, from the start of code to the end of bss and noinit.
+pub fn kernel_image_region() -> RangeInclusive<usize> {
+    RangeInclusive::new(code_start(), data_end_exclusive() - 1)
+}
@@ -1,34 +1,54 @@
-PAGE_SIZE = 64K;
+/* The translation granule, 64 KiB or 4 KiB with the granule_4k feature. Set by build.rs. */
+PAGE_SIZE = __page_size;
 PAGE_MASK = PAGE_SIZE - 1;
 
 __rpi_phys_dram_start_addr = 0;
 
+/* The kernel is linked to the top of the virtual address space and loaded at the physical
+ * address below it. Must match ros_sys::memory::KERNEL_VIRT_OFFSET. */
+__kernel_virt_offset = 0xffffffff00000000;
+
 __rpi_phys_binary_load_addr = 0x80000;
 
+/* Must match ros_sys::memory::KERNEL_STACK_SIZE. */
+__boot_core_stack_size = 256K;
+
 ENTRY(__rpi_phys_binary_load_addr)
 PHDRS
 {
     segment_boot_core_stack PT_LOAD FLAGS(6);
     segment_code PT_LOAD FLAGS(5);
     segment_data PT_LOAD FLAGS(6);
+    segment_heap PT_LOAD FLAGS(6);
 }
 
 SECTIONS
 {
-    . =  __rpi_phys_dram_start_addr;
+    . = __kernel_virt_offset + __rpi_phys_dram_start_addr;
 
     /* Boot Core Stack */
-    .boot_core_stack (NOLOAD) :
+    .boot_core_stack (NOLOAD) : AT(ADDR(.boot_core_stack) - __kernel_virt_offset)
     {
-        . += __rpi_phys_binary_load_addr;
+        __boot_reserved_start = .;
+        . += __rpi_phys_binary_load_addr - __boot_core_stack_size - PAGE_SIZE;
+
+        /* Unmapped, so that a stack overflow faults */
+        __boot_core_stack_guard_page_start = .;
+        . += PAGE_SIZE;
+        __boot_core_stack_guard_page_end_exclusive = .;
+
+        __boot_core_stack_start = .;
+        . += __boot_core_stack_size;
         __boot_core_stack_end_exclusive = .;
     } :segment_boot_core_stack
 
     ASSERT((. & PAGE_MASK) == 0, "End of boot core stack is not page aligned")
+    ASSERT((__boot_core_stack_start & (2 * __boot_core_stack_size - 1)) == __boot_core_stack_size,
+        "Boot core stack is not the upper half of a block twice its size")
 
     /* Code + RO Data + Global Offset Table */
     __code_start = .;
-    .text :
+    .text : AT(ADDR(.text) - __kernel_virt_offset)
     {
         KEEP(*(.text._start))
         *(.text._start_arguments)
@@ -36,15 +56,16 @@ SECTIONS
         *(.text*)
     } :segment_code
 
-    .rodata : ALIGN(8) { *(.rodata*) } :segment_code
+    .rodata : AT(ADDR(.rodata) - __kernel_virt_offset) ALIGN(8) { *(.rodata*) } :segment_code
 
     . = ALIGN(PAGE_SIZE);
     __code_end_exclusive = .;
 
     /* Data + BSS */
-    .data : { *(.data*) } :segment_data
+    __data_start = .;
+    .data : AT(ADDR(.data) - __kernel_virt_offset) { *(.data*) } :segment_data
 
-    .bss (NOLOAD) : ALIGN(16)
+    .bss (NOLOAD) : AT(ADDR(.bss) - __kernel_virt_offset) ALIGN(16)
     {
         __bss_start = .;
         *(.bss*);
@@ -52,7 +73,26 @@ SECTIONS
         __bss_end_exclusive = .;
     } :segment_data
 
-    .got : { *(.got*) }
+    /* Neither loaded nor zeroed, so it keeps its content over resets that leave the RAM powered */
+    .noinit (NOLOAD) : AT(ADDR(.noinit) - __kernel_virt_offset) ALIGN(16)
+    {
+        *(.noinit*);
+    } :segment_data
+
+    . = ALIGN(PAGE_SIZE);
+    __data_end_exclusive = .;
+
+    /* Kernel Heap */
+    .heap (NOLOAD) : AT(ADDR(.heap) - __kernel_virt_offset) ALIGN(PAGE_SIZE)
+    {
+        __heap_start = .;
+        . += 16 * 1024 * 1024;
+        __heap_end_exclusive = .;
+    } :segment_heap
+
+    ASSERT((. & PAGE_MASK) == 0, "End of kernel heap is not page aligned")
+
+    .got : AT(ADDR(.got) - __kernel_virt_offset) { *(.got*) }
     ASSERT(SIZEOF(.got) == 0, "Relocation support not expected")
 
     /DISCARD/ : { *(.comment*) }
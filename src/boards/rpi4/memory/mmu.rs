@@ -1,52 +1,126 @@
 use core::ops::RangeInclusive;
 
-use super::map as memory_map;
-use crate::memory::mmu::{
-    AccessPermissions, AddressSpace, AttributeFields, KernelVirtualLayout, MemAttributes,
-    Translation, TranslationDescriptor,
+use ros_sys::memory::{
+    mmu::{AccessPermissions, AttributeFields, MemAttributes, Translation, TranslationDescriptor},
+    phys_to_virt,
 };
 
-/// The kernel's address space defined by this BSP.
-pub type KernelAddrSpace = AddressSpace<{ memory_map::END_INCLUSIVE + 1 }>;
+use super::map as memory_map;
 
-const NUM_MEM_RANGES: usize = 2;
+/// The last (inclusive) address of the kernel's virtual address space.
+pub const VIRT_ADDR_SPACE_END_INCLUSIVE: usize = phys_to_virt(memory_map::END_INCLUSIVE);
 
 fn code_range_inclusive() -> RangeInclusive<usize> {
     RangeInclusive::new(super::code_start(), super::code_end_exclusive() - 1)
 }
 
-fn mmio_range_inclusive() -> RangeInclusive<usize> {
-    RangeInclusive::new(memory_map::mmio::BASE, memory_map::mmio::END_INCLUSIVE)
+fn boot_core_stack_guard_page_range_inclusive() -> RangeInclusive<usize> {
+    super::boot_core_stack_guard_page_region()
+}
+
+fn boot_core_stack_range_inclusive() -> RangeInclusive<usize> {
+    super::boot_core_stack_region()
+}
+
+fn data_range_inclusive() -> RangeInclusive<usize> {
+    super::data_region()
+}
+
+fn heap_range_inclusive() -> RangeInclusive<usize> {
+    super::heap_region()
+}
+
+/// Listed so that it is mapped down to pages. Remapping a page of it must not split a block, as
+/// the break-before-make sequence would unmap the rest of the block while it is in use.
+fn free_dram_range_inclusive() -> RangeInclusive<usize> {
+    super::free_dram_region()
+}
+
+/// The window `mmio_remap()` maps device MMIO into on demand, handing out its addresses in the
+/// order of the requests. It takes the addresses the linear mapping would use for MMIO, so it is as
+/// large as the MMIO region.
+pub fn mmio_remap_range_inclusive() -> RangeInclusive<usize> {
+    RangeInclusive::new(
+        phys_to_virt(memory_map::mmio::BASE),
+        phys_to_virt(memory_map::mmio::END_INCLUSIVE),
+    )
 }
 
 /// The virtual memory layout.
-pub static LAYOUT: KernelVirtualLayout<NUM_MEM_RANGES> = KernelVirtualLayout::new(
-    memory_map::END_INCLUSIVE,
-    [
-        TranslationDescriptor {
-            name: "Kernel code and RO data",
-            virtual_range: code_range_inclusive,
-            physical_range_translation: Translation::Identity,
-            attribute_fields: AttributeFields {
-                mem_attributes: MemAttributes::CacheableDram,
-                acc_perms: AccessPermissions::ReadOnly,
-                execute_never: false,
-            },
+pub static LAYOUT: &[TranslationDescriptor] = &[
+    TranslationDescriptor {
+        name: "Boot core stack guard page",
+        virtual_range: boot_core_stack_guard_page_range_inclusive,
+        physical_range_translation: Translation::Unmapped,
+        attribute_fields: AttributeFields {
+            mem_attributes: MemAttributes::CacheableDram,
+            acc_perms: AccessPermissions::ReadOnly,
+            execute_never: true,
+        },
+    },
+    TranslationDescriptor {
+        name: "Boot core stack",
+        virtual_range: boot_core_stack_range_inclusive,
+        physical_range_translation: Translation::Linear,
+        attribute_fields: AttributeFields {
+            mem_attributes: MemAttributes::CacheableDram,
+            acc_perms: AccessPermissions::ReadWrite,
+            execute_never: true,
+        },
+    },
+    TranslationDescriptor {
+        name: "Kernel code and RO data",
+        virtual_range: code_range_inclusive,
+        physical_range_translation: Translation::Linear,
+        attribute_fields: AttributeFields {
+            mem_attributes: MemAttributes::CacheableDram,
+            acc_perms: AccessPermissions::ReadOnly,
+            execute_never: false,
+        },
+    },
+    TranslationDescriptor {
+        name: "Kernel data and bss",
+        virtual_range: data_range_inclusive,
+        physical_range_translation: Translation::Linear,
+        attribute_fields: AttributeFields {
+            mem_attributes: MemAttributes::CacheableDram,
+            acc_perms: AccessPermissions::ReadWrite,
+            execute_never: true,
+        },
+    },
+    TranslationDescriptor {
+        name: "Kernel heap",
+        virtual_range: heap_range_inclusive,
+        physical_range_translation: Translation::Linear,
+        attribute_fields: AttributeFields {
+            mem_attributes: MemAttributes::CacheableDram,
+            acc_perms: AccessPermissions::ReadWrite,
+            execute_never: true,
+        },
+    },
+    TranslationDescriptor {
+        name: "Free DRAM",
+        virtual_range: free_dram_range_inclusive,
+        physical_range_translation: Translation::Linear,
+        attribute_fields: AttributeFields {
+            mem_attributes: MemAttributes::CacheableDram,
+            acc_perms: AccessPermissions::ReadWrite,
+            execute_never: true,
         },
-        TranslationDescriptor {
-            name: "Device MMIO",
-            virtual_range: mmio_range_inclusive,
-            physical_range_translation: Translation::Identity,
-            attribute_fields: AttributeFields {
-                mem_attributes: MemAttributes::Device,
-                acc_perms: AccessPermissions::ReadWrite,
-                execute_never: true,
-            },
+    },
+    TranslationDescriptor {
+        name: "MMIO remap window",
+        virtual_range: mmio_remap_range_inclusive,
+        physical_range_translation: Translation::Unmapped,
+        attribute_fields: AttributeFields {
+            mem_attributes: MemAttributes::Device,
+            acc_perms: AccessPermissions::ReadWrite,
+            execute_never: true,
         },
-    ],
-);
+    },
+];
 
 /// Return a reference to the virtual memory layout.
-pub fn virt_mem_layout() -> &'static KernelVirtualLayout<NUM_MEM_RANGES> {
-    &LAYOUT
+pub fn virt_mem_layout() -> &'static [TranslationDescriptor] {
+    LAYOUT
 }
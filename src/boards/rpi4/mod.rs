@@ -1,6 +1,16 @@
-use core::sync::atomic::{AtomicBool, Ordering};
+use core::{
+    ops::RangeInclusive,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
 
-use ros_sys::{board, console, drivers::arm, exception};
+use ros_sys::{
+    board, console, cpu,
+    drivers::arm,
+    exception,
+    memory::{frame_alloc, heap_alloc, mmu::TranslationDescriptor, virt_to_phys},
+    timer_manager,
+};
 
 use crate::{
     boards::rpi4::memory::map::mmio,
@@ -17,12 +27,31 @@ pub mod memory;
 pub(in crate::boards::rpi4) mod irq_map {
     use ros_sys::drivers::arm::IrqNumber;
 
+    pub const ARCH_TIMER: IrqNumber = IrqNumber::new(30);
+    pub const SYSTEM_TIMER: IrqNumber = IrqNumber::new(97);
     pub const PL011_UART: IrqNumber = IrqNumber::new(153);
 }
 
+/// The firmware parks the secondary cores polling these addresses, one for each core.
+const SPIN_TABLE: [(usize, usize); 3] = [(1, 0xe0), (2, 0xe8), (3, 0xf0)];
+
 static GPIO: drivers::gpio::bcm2711_gpio::Bcm2711Gpio =
     unsafe { drivers::gpio::bcm2711_gpio::Bcm2711Gpio::new(mmio::GPIO_BASE) };
 
+pub static SYSTEM_TIMER: drivers::timer::bcm2711_system_timer::Bcm2711SystemTimer = unsafe {
+    drivers::timer::bcm2711_system_timer::Bcm2711SystemTimer::new(mmio::SYSTEM_TIMER_BASE)
+};
+
+pub static WATCHDOG: drivers::watchdog::bcm2711_watchdog::Bcm2711Watchdog =
+    unsafe { drivers::watchdog::bcm2711_watchdog::Bcm2711Watchdog::new(mmio::PM_BASE) };
+
+/// The board resets if the watchdog isn't petted for this long.
+const WATCHDOG_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The watchdog is petted from the timer interrupt. It bites if IRQs stay masked, or an IRQ
+/// handler doesn't return.
+const WATCHDOG_PET_PERIOD: Duration = Duration::from_secs(1);
+
 static PL011_UART: drivers::serial::pl011_uart::Pl011Uart =
     unsafe { drivers::serial::pl011_uart::Pl011Uart::new(mmio::UART_BASE) };
 
@@ -64,6 +93,55 @@ fn init_uart() -> Result<(), &'static str> {
     Ok(())
 }
 
+fn init_arch_timer() -> Result<(), &'static str> {
+    let timer_desc = driver_manager::DeviceDriverDescriptor::new(
+        timer_manager::timer_manager(),
+        None,
+        Some(irq_map::ARCH_TIMER),
+    );
+    driver_manager::driver_manager().register_driver(timer_desc);
+
+    Ok(())
+}
+
+fn system_timer_config() -> Result<(), &'static str> {
+    #[cfg(feature = "system_timer_clock")]
+    timer_manager::timer_manager().register_clock(&SYSTEM_TIMER);
+
+    Ok(())
+}
+
+fn init_system_timer() -> Result<(), &'static str> {
+    let timer_desc = driver_manager::DeviceDriverDescriptor::new(
+        &SYSTEM_TIMER,
+        Some(system_timer_config),
+        Some(irq_map::SYSTEM_TIMER),
+    );
+    driver_manager::driver_manager().register_driver(timer_desc);
+
+    Ok(())
+}
+
+fn watchdog_config() -> Result<(), &'static str> {
+    if !cfg!(feature = "watchdog") {
+        return Ok(());
+    }
+
+    WATCHDOG.start(WATCHDOG_TIMEOUT)?;
+
+    timer_manager::timer_manager()
+        .set_timeout_periodic(WATCHDOG_PET_PERIOD, || WATCHDOG.pet())
+        .map(|_| ())
+}
+
+fn init_watchdog() -> Result<(), &'static str> {
+    let watchdog_desc =
+        driver_manager::DeviceDriverDescriptor::new(&WATCHDOG, Some(watchdog_config), None);
+    driver_manager::driver_manager().register_driver(watchdog_desc);
+
+    Ok(())
+}
+
 fn post_init_interrupt_controller() -> Result<(), &'static str> {
     exception::asynchronous::register_irq_manager(&INTERRUPT_CONTROLLER);
 
@@ -89,24 +167,87 @@ impl board::interface::Info for Rpi4Board {
     }
 }
 
+impl board::interface::Memory for Rpi4Board {
+    fn virt_addr_space_end_inclusive(&self) -> usize {
+        memory::mmu::VIRT_ADDR_SPACE_END_INCLUSIVE
+    }
+
+    fn virt_mem_layout(&self) -> &'static [TranslationDescriptor] {
+        memory::mmu::virt_mem_layout()
+    }
+
+    fn virt_mmio_remap_region(&self) -> RangeInclusive<usize> {
+        memory::mmu::mmio_remap_range_inclusive()
+    }
+}
+
+impl board::interface::Power for Rpi4Board {
+    fn reboot(&self) -> ! {
+        WATCHDOG.reset()
+    }
+
+    fn power_off(&self) -> ! {
+        // The RPi4 can't cut its own power. The firmware halting is as close as it gets.
+        WATCHDOG.halt()
+    }
+}
+
 impl board::interface::All for Rpi4Board {}
 
 static RPI4_BOARD: Rpi4Board = Rpi4Board {};
 
+/// Make the board description available. Must run before the MMU is enabled, which takes the
+/// memory layout from it.
+pub fn board_register() {
+    board::register_board(&RPI4_BOARD);
+}
+
 pub unsafe fn board_init() -> Result<(), &'static str> {
     static INIT_DONE: AtomicBool = AtomicBool::new(false);
     if INIT_DONE.load(Ordering::Relaxed) {
         return Err("Init already done");
     }
 
+    heap_alloc::kernel_heap_allocator().init(memory::heap_region())?;
+
+    // The frame allocator works on physical addresses, the linker regions are virtual.
+    let phys_region = |region: RangeInclusive<usize>| {
+        RangeInclusive::new(virt_to_phys(*region.start()), virt_to_phys(*region.end()))
+    };
+
+    frame_alloc::frame_allocator().init(
+        &[memory::dram_region()],
+        &[
+            phys_region(memory::boot_reserved_region()),
+            phys_region(memory::kernel_image_region()),
+            phys_region(memory::heap_region()),
+        ],
+    )?;
+
     init_gpio()?;
 
     init_uart()?;
 
-    init_interrupt_controller()?;
+    init_arch_timer()?;
 
-    board::register_board(&RPI4_BOARD);
+    init_system_timer()?;
+
+    init_watchdog()?;
+
+    init_interrupt_controller()?;
 
     INIT_DONE.store(true, Ordering::Relaxed);
     Ok(())
 }
+
+/// Init of a secondary core. Of the devices, only the GIC has per-core state: its CPU interface.
+pub unsafe fn board_secondary_init() -> Result<(), &'static str> {
+    use driver_manager::interface::DeviceDriver;
+
+    INTERRUPT_CONTROLLER.init()
+}
+
+/// Release the secondary cores from the firmware.
+pub unsafe fn start_secondary_cores() -> Result<(), &'static str> {
+    cpu::smp::start_secondary_cores(&SPIN_TABLE)
+}
@@ -1,2 +1,4 @@
 pub mod gpio;
 pub mod serial;
+pub mod timer;
+pub mod watchdog;
@@ -200,4 +200,8 @@ impl DeviceDriver for Bcm2711Gpio {
     fn compatible(&self) -> &'static str {
         Self::COMPATIBLE
     }
+
+    unsafe fn init(&self) -> Result<(), &'static str> {
+        self.inner.lock(|inner| inner.register.map())
+    }
 }
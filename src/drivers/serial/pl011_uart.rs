@@ -10,10 +10,14 @@ use ros_sys::{console, cpu, drivers::common::MmioDerefWrapper, exception};
 
 use crate::{driver_manager, drivers::serial::interface};
 
-use ros_sys::synchronization::{interface::Mutex, IrqSafeNullLock};
+use ros_sys::synchronization::{interface::Mutex, IrqSafeSpinLock};
 
 pub const UART_CLOCK: u32 = 48_000_000;
 
+/// How often `force_write_fmt()` tries the lock before writing without it. Long enough for another
+/// core to finish a line.
+const FORCE_WRITE_TRIES: usize = 1 << 20;
+
 register_bitfields![
     u32,
 
@@ -213,13 +217,18 @@ impl Pl011UartInner {
             .modify(CR::UARTEN::SET + CR::TXE::SET + CR::RXE::SET);
     }
 
-    /// Send a character.
-    fn write_char(&mut self, c: char) {
+    /// Send a character without counting it.
+    fn put_char(&self, c: char) {
         while self.registers.fr.matches_all(FR::TXFF::SET) {
             cpu::nop();
         }
 
         self.registers.dr.set(c as u32);
+    }
+
+    /// Send a character.
+    fn write_char(&mut self, c: char) {
+        self.put_char(c);
 
         self.chars_written += 1;
     }
@@ -259,8 +268,21 @@ impl fmt::Write for Pl011UartInner {
     }
 }
 
+/// Writes through a `Pl011UartInner` whose lock could not be taken. Only touches registers.
+struct ForcedWriter<'a>(&'a Pl011UartInner);
+
+impl fmt::Write for ForcedWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            self.0.put_char(c);
+        }
+
+        Ok(())
+    }
+}
+
 pub struct Pl011Uart {
-    inner: IrqSafeNullLock<Pl011UartInner>,
+    inner: IrqSafeSpinLock<Pl011UartInner>,
 }
 
 impl Pl011Uart {
@@ -269,7 +291,7 @@ impl Pl011Uart {
     /// Create an instance.
     pub const unsafe fn new(mmio_base_addr: usize) -> Self {
         Self {
-            inner: IrqSafeNullLock::new(Pl011UartInner::new(mmio_base_addr)),
+            inner: IrqSafeSpinLock::new(Pl011UartInner::new(mmio_base_addr)),
         }
     }
 }
@@ -287,6 +309,10 @@ impl driver_manager::interface::DeviceDriver for Pl011Uart {
         Self::COMPATIBLE
     }
 
+    unsafe fn init(&self) -> Result<(), &'static str> {
+        self.inner.lock(|inner| inner.registers.map())
+    }
+
     fn register_and_enable_irq_handler(
         &'static self,
         irq_number: &Self::IrqNumberType,
@@ -311,6 +337,21 @@ impl console::interface::Write for Pl011Uart {
         self.inner.lock(|inner| inner.write_fmt(args))
     }
 
+    fn force_write_fmt(&self, args: fmt::Arguments) -> fmt::Result {
+        for _ in 0..FORCE_WRITE_TRIES {
+            if let Some(mut inner) = self.inner.try_lock_guard() {
+                return inner.write_fmt(args);
+            }
+
+            cpu::nop();
+        }
+
+        // The holder is stuck, or was interrupted on this core. Its output and this one may mix.
+        let inner = unsafe { &*self.inner.data_ptr() };
+
+        ForcedWriter(inner).write_fmt(args)
+    }
+
     fn flush(&self) {
         self.inner.lock(|inner| inner.flush());
     }
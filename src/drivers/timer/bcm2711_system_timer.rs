@@ -0,0 +1,185 @@
+//! BCM2711 System Timer: a free-running 1 MHz counter with four compare channels.
+
+use core::time::Duration;
+
+use aarch64_cpu::registers::{Readable, Writeable};
+use tock_registers::{
+    register_bitfields, register_structs,
+    registers::{ReadOnly, ReadWrite},
+};
+
+use ros_sys::{
+    drivers::common::MmioDerefWrapper,
+    exception,
+    synchronization::{interface::Mutex, IrqSafeNullLock},
+    timer_manager,
+};
+
+use crate::driver_manager;
+
+/// Channels 0 and 2 belong to the VideoCore.
+const COMPARE_CHANNEL: usize = 1;
+
+register_bitfields![
+    u32,
+
+    /// Control/Status. A match bit is set when the counter reaches the channel's compare value,
+    /// and cleared by writing 1.
+    CS [
+        M3 OFFSET(3) NUMBITS(1) [],
+        M2 OFFSET(2) NUMBITS(1) [],
+        M1 OFFSET(1) NUMBITS(1) [],
+        M0 OFFSET(0) NUMBITS(1) []
+    ]
+];
+
+register_structs! {
+    RegisterBlock {
+        (0x00 => cs: ReadWrite<u32, CS::Register>),
+        (0x04 => clo: ReadOnly<u32>),
+        (0x08 => chi: ReadOnly<u32>),
+        (0x0c => c: [ReadWrite<u32>; 4]),
+        (0x1c => @END),
+    }
+}
+
+/// Abstraction for the associated MMIO registers.
+type Registers = MmioDerefWrapper<RegisterBlock>;
+
+struct Bcm2711SystemTimerInner {
+    registers: Registers,
+}
+
+impl Bcm2711SystemTimerInner {
+    /// Create an instance.
+    pub const unsafe fn new(mmio_base_addr: usize) -> Self {
+        Self {
+            registers: Registers::new(mmio_base_addr),
+        }
+    }
+
+    /// Read the 64 bit counter, which ticks once per microsecond. Retry if the upper half changed meanwhile.
+    fn counter(&self) -> u64 {
+        loop {
+            let hi = self.registers.chi.get();
+            let lo = self.registers.clo.get();
+
+            if self.registers.chi.get() == hi {
+                return ((hi as u64) << 32) | lo as u64;
+            }
+        }
+    }
+
+    /// Let the channel match at `ticks`. Only the lower 32 bits are compared, so deadlines more
+    /// than 2^32 ticks away match early, which timeouts tolerate.
+    fn set_compare(&self, ticks: u64) {
+        loop {
+            // A compare value the counter already passed would only match after wrapping.
+            let target = ticks.max(self.counter() + 1);
+            self.registers.c[COMPARE_CHANNEL].set(target as u32);
+
+            if self.counter() < target {
+                break;
+            }
+        }
+    }
+
+    /// Move the compare value as far away as possible, and clear a pending match. The channel
+    /// can't be turned off, so it still matches once the counter gets there, 2^32 ticks later.
+    fn clear_compare(&self) {
+        let ticks = self.counter().wrapping_sub(1);
+
+        self.registers.c[COMPARE_CHANNEL].set(ticks as u32);
+        self.clear_match();
+    }
+
+    fn clear_match(&self) {
+        self.registers.cs.write(CS::M1::SET);
+    }
+}
+
+/// Representation of the System Timer HW.
+pub struct Bcm2711SystemTimer {
+    inner: IrqSafeNullLock<Bcm2711SystemTimerInner>,
+}
+
+impl Bcm2711SystemTimer {
+    pub const COMPATIBLE: &'static str = "BCM2711 System Timer";
+
+    /// Create an instance.
+    /// # Safety
+    pub const unsafe fn new(mmio_base_addr: usize) -> Self {
+        Self {
+            inner: IrqSafeNullLock::new(Bcm2711SystemTimerInner::new(mmio_base_addr)),
+        }
+    }
+}
+
+impl timer_manager::interface::Clock for Bcm2711SystemTimer {
+    fn name(&self) -> &'static str {
+        Self::COMPATIBLE
+    }
+
+    fn uptime(&self) -> Duration {
+        Duration::from_micros(self.inner.lock(|inner| inner.counter()))
+    }
+
+    fn set_deadline(&self, deadline: Duration) {
+        // Round up, so the deadline is never missed by a fraction of a tick.
+        let ticks = deadline.as_nanos().div_ceil(1000) as u64;
+
+        self.inner.lock(|inner| inner.set_compare(ticks));
+    }
+
+    fn clear_deadline(&self) {
+        self.inner.lock(|inner| inner.clear_compare());
+    }
+}
+
+impl driver_manager::interface::DeviceDriver for Bcm2711SystemTimer {
+    type IrqNumberType = exception::asynchronous::IrqNumber;
+
+    fn compatible(&self) -> &'static str {
+        Self::COMPATIBLE
+    }
+
+    unsafe fn init(&self) -> Result<(), &'static str> {
+        self.inner.lock(|inner| {
+            inner.registers.map()?;
+            inner.clear_compare();
+
+            Ok(())
+        })
+    }
+
+    fn register_and_enable_irq_handler(
+        &'static self,
+        irq_number: &Self::IrqNumberType,
+    ) -> Result<(), &'static str> {
+        use exception::asynchronous::{irq_manager, IrqHandlerDescriptor};
+
+        let descriptor = IrqHandlerDescriptor::new(*irq_number, Self::COMPATIBLE, self);
+        irq_manager().register_handler(descriptor)?;
+
+        // The compare channel always matches eventually, so the interrupt is only enabled if
+        // timeouts run on this clock. The clock is chosen in the post-init callbacks, which ran
+        // already.
+        let clock = timer_manager::timer_manager().clock();
+        if core::ptr::addr_eq(clock, self) {
+            irq_manager().enable(irq_number);
+        }
+
+        Ok(())
+    }
+}
+
+impl exception::asynchronous::interface::IrqHandler for Bcm2711SystemTimer {
+    fn handle(&self) -> Result<(), &'static str> {
+        self.inner.lock(|inner| inner.clear_match());
+
+        // Only enabled while timeouts run on this clock.
+        timer_manager::timer_manager().handle_timeouts();
+
+        Ok(())
+    }
+}
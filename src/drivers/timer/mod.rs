@@ -0,0 +1 @@
+pub mod bcm2711_system_timer;
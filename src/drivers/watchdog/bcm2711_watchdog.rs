@@ -0,0 +1,241 @@
+//! BCM2711 watchdog, part of the power management block. When it expires, the SoC resets.
+
+use core::{fmt, time::Duration};
+
+use aarch64_cpu::registers::{ReadWriteable, Readable, Writeable};
+use tock_registers::{register_bitfields, register_structs, registers::ReadWrite};
+
+use ros_sys::{
+    cpu,
+    drivers::common::MmioDerefWrapper,
+    exception,
+    synchronization::{interface::Mutex, IrqSafeNullLock},
+};
+
+use crate::driver_manager;
+
+/// Every write to the PM registers must carry this in the upper byte.
+const PASSWORD: u32 = 0x5a;
+
+/// The watchdog counts down at 65536 Hz.
+const TICKS_PER_SEC: u64 = 1 << 16;
+
+/// The watchdog timeout that resets right away, in ticks.
+const RESET_TICKS: u32 = 10;
+
+/// The partition the firmware boots from after a reset, spread over the even bits 0 to 10 of
+/// PM_RSTS.
+const RSTS_PARTITION_MASK: u32 = 0x555;
+
+/// The partition that makes the firmware halt instead of booting.
+const RSTS_PARTITION_HALT: u32 = 0x555;
+
+register_bitfields![
+    u32,
+
+    /// Reset control. Bits outside WRCFG configure other resets, so they are written back as read.
+    PM_RSTC [
+        PASSWD OFFSET(24) NUMBITS(8) [],
+
+        /// What happens when the watchdog expires.
+        WRCFG OFFSET(4) NUMBITS(2) [
+            Clear = 0b00,
+            FullReset = 0b10
+        ]
+    ],
+
+    /// Reset status. Tells what caused the last reset.
+    PM_RSTS [
+        /// Power-on reset.
+        HADPOR OFFSET(12) NUMBITS(1) [],
+
+        /// Watchdog resets, full and hard.
+        HADWRF OFFSET(5) NUMBITS(1) [],
+        HADWRH OFFSET(6) NUMBITS(1) []
+    ],
+
+    /// Watchdog timer.
+    PM_WDOG [
+        PASSWD OFFSET(24) NUMBITS(8) [],
+        TIME OFFSET(0) NUMBITS(20) []
+    ]
+];
+
+register_structs! {
+    RegisterBlock {
+        (0x00 => _reserved1),
+        (0x1c => rstc: ReadWrite<u32, PM_RSTC::Register>),
+        (0x20 => rsts: ReadWrite<u32, PM_RSTS::Register>),
+        (0x24 => wdog: ReadWrite<u32, PM_WDOG::Register>),
+        (0x28 => @END),
+    }
+}
+
+/// Abstraction for the associated MMIO registers.
+type Registers = MmioDerefWrapper<RegisterBlock>;
+
+/// What caused the last reset.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ResetReason {
+    PowerOn,
+
+    /// The watchdog expired. A software reboot through the watchdog shows up the same.
+    Watchdog,
+
+    Other,
+}
+
+impl fmt::Display for ResetReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            ResetReason::PowerOn => "power-on",
+            ResetReason::Watchdog => "watchdog",
+            ResetReason::Other => "other",
+        };
+
+        write!(f, "{}", s)
+    }
+}
+
+struct Bcm2711WatchdogInner {
+    registers: Registers,
+
+    /// The timeout in watchdog ticks while running.
+    ticks: Option<u32>,
+}
+
+impl Bcm2711WatchdogInner {
+    /// Create an instance.
+    pub const unsafe fn new(mmio_base_addr: usize) -> Self {
+        Self {
+            registers: Registers::new(mmio_base_addr),
+            ticks: None,
+        }
+    }
+
+    fn start(&mut self, ticks: u32) {
+        self.registers
+            .wdog
+            .write(PM_WDOG::PASSWD.val(PASSWORD) + PM_WDOG::TIME.val(ticks));
+
+        self.registers
+            .rstc
+            .modify(PM_RSTC::PASSWD.val(PASSWORD) + PM_RSTC::WRCFG::FullReset);
+
+        self.ticks = Some(ticks);
+    }
+
+    fn stop(&mut self) {
+        self.registers
+            .rstc
+            .modify(PM_RSTC::PASSWD.val(PASSWORD) + PM_RSTC::WRCFG::Clear);
+
+        self.ticks = None;
+    }
+
+    /// Make the firmware boot from `partition` after the next reset.
+    fn set_boot_partition(&self, partition: u32) {
+        let rsts = self.registers.rsts.get() & !RSTS_PARTITION_MASK & !(0xff << 24);
+
+        self.registers
+            .rsts
+            .set((PASSWORD << 24) | rsts | (partition & RSTS_PARTITION_MASK));
+    }
+
+    fn reset_reason(&self) -> ResetReason {
+        let rsts = self.registers.rsts.extract();
+
+        if rsts.is_set(PM_RSTS::HADWRF) || rsts.is_set(PM_RSTS::HADWRH) {
+            ResetReason::Watchdog
+        } else if rsts.is_set(PM_RSTS::HADPOR) {
+            ResetReason::PowerOn
+        } else {
+            ResetReason::Other
+        }
+    }
+}
+
+/// Representation of the watchdog HW.
+pub struct Bcm2711Watchdog {
+    inner: IrqSafeNullLock<Bcm2711WatchdogInner>,
+}
+
+impl Bcm2711Watchdog {
+    pub const COMPATIBLE: &'static str = "BCM2711 PM Watchdog";
+
+    /// The counter holds a bit less than 16 seconds.
+    pub const MAX_TIMEOUT: Duration = Duration::from_secs(15);
+
+    /// Create an instance.
+    /// # Safety
+    pub const unsafe fn new(mmio_base_addr: usize) -> Self {
+        Self {
+            inner: IrqSafeNullLock::new(Bcm2711WatchdogInner::new(mmio_base_addr)),
+        }
+    }
+
+    /// Reset the SoC unless `pet()` is called within `timeout`.
+    pub fn start(&self, timeout: Duration) -> Result<(), &'static str> {
+        if timeout > Self::MAX_TIMEOUT {
+            return Err("Watchdog timeout too long");
+        }
+
+        let ticks = (timeout.as_micros() as u64 * TICKS_PER_SEC / 1_000_000).max(1) as u32;
+        self.inner.lock(|inner| inner.start(ticks));
+
+        Ok(())
+    }
+
+    /// Restart the countdown of a running watchdog.
+    pub fn pet(&self) {
+        self.inner.lock(|inner| {
+            if let Some(ticks) = inner.ticks {
+                inner.start(ticks);
+            }
+        });
+    }
+
+    /// Stop the watchdog.
+    pub fn stop(&self) {
+        self.inner.lock(|inner| inner.stop());
+    }
+
+    /// Whether the watchdog is running.
+    pub fn is_running(&self) -> bool {
+        self.inner.lock(|inner| inner.ticks.is_some())
+    }
+
+    /// What caused the last reset.
+    pub fn reset_reason(&self) -> ResetReason {
+        self.inner.lock(|inner| inner.reset_reason())
+    }
+
+    /// Reset the SoC right away.
+    pub fn reset(&self) -> ! {
+        self.inner.lock(|inner| inner.start(RESET_TICKS));
+
+        cpu::wait_forever()
+    }
+
+    /// Reset the SoC into the firmware, which halts instead of booting. QEMU powers off.
+    pub fn halt(&self) -> ! {
+        self.inner.lock(|inner| {
+            inner.set_boot_partition(RSTS_PARTITION_HALT);
+            inner.start(RESET_TICKS);
+        });
+
+        cpu::wait_forever()
+    }
+}
+
+impl driver_manager::interface::DeviceDriver for Bcm2711Watchdog {
+    type IrqNumberType = exception::asynchronous::IrqNumber;
+
+    fn compatible(&self) -> &'static str {
+        Self::COMPATIBLE
+    }
+
+    unsafe fn init(&self) -> Result<(), &'static str> {
+        self.inner.lock(|inner| inner.registers.map())
+    }
+}
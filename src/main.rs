@@ -3,27 +3,482 @@
 #![no_std]
 #![no_main]
 
+extern crate alloc;
+
+use alloc::vec::Vec;
 use core::time::Duration;
 
-use ros_sys::{board, cpu, driver_manager, exception, info, timer_manager};
+use ros_sys::{
+    board, cpu, driver_manager, exception, info,
+    memory::{frame_alloc, heap_alloc},
+    timer_manager,
+};
 
 mod boards;
 mod drivers;
-mod memory;
+
+/// How long a panic message stays up before the board reboots, with the `panic_reboot` feature.
+const PANIC_REBOOT_DELAY: Duration = Duration::from_secs(5);
 
 #[no_mangle]
 unsafe fn board_early_init() -> Result<(), &'static str> {
-    use memory::mmu::interface::Mmu;
+    use ros_sys::memory::mmu::interface::Mmu;
 
     exception::handling_init();
 
-    if let Err(str) = memory::mmu::mmu().enable_mmu_and_caching() {
+    boards::rpi4::board_register();
+
+    if let Err(str) = ros_sys::memory::mmu::mmu().enable_mmu_and_caching() {
         panic!("MMU: {}", str);
     }
 
     boards::rpi4::board_init()
 }
 
+#[no_mangle]
+unsafe fn board_secondary_init() -> Result<(), &'static str> {
+    use ros_sys::memory::mmu::interface::Mmu;
+
+    exception::handling_init();
+
+    if let Err(str) = ros_sys::memory::mmu::mmu().enable_mmu_and_caching_secondary() {
+        panic!("MMU: {}", str);
+    }
+
+    boards::rpi4::board_secondary_init()
+}
+
+/// Free frames the wrong way. Double frees and frames the allocator never managed, like the
+/// armstub at 0 and MMIO, are refused.
+fn frame_alloc_test() {
+    let frames = frame_alloc::frame_allocator();
+    let frame = frames
+        .alloc_frame()
+        .expect("Frame allocator test: no frame");
+
+    frames.free_frames(frame, 1).unwrap();
+    assert!(frames.free_frames(frame, 1).is_err());
+    assert!(frames.free_frames(0, 1).is_err());
+    assert!(frames.free_frames(0xfe00_0000, 1).is_err());
+}
+
+/// Map a frame at a second virtual address and check that both addresses see the same data.
+fn mmu_remap_test() {
+    use ros_sys::memory::{
+        mmu::{interface::Mmu, AttributeFields},
+        phys_to_virt,
+    };
+
+    let frames = frame_alloc::frame_allocator();
+    let data = frames.alloc_frame().expect("MMU remap test: no frame");
+    let alias = frames.alloc_frame().expect("MMU remap test: no frame");
+
+    unsafe {
+        core::ptr::write_volatile(phys_to_virt(data) as *mut u64, 0x5a5a_a5a5);
+
+        ros_sys::memory::mmu::mmu()
+            .map_pages(phys_to_virt(alias), data, 1, &AttributeFields::default())
+            .expect("MMU remap test: map failed");
+        assert_eq!(
+            core::ptr::read_volatile(phys_to_virt(alias) as *const u64),
+            0x5a5a_a5a5
+        );
+
+        // Restore the linear mapping.
+        ros_sys::memory::mmu::mmu()
+            .map_pages(phys_to_virt(alias), alias, 1, &AttributeFields::default())
+            .expect("MMU remap test: restore failed");
+    }
+
+    frames.free_frames(data, 1).unwrap();
+    frames.free_frames(alias, 1).unwrap();
+}
+
+/// Copy through the fault fixups. Reading a null pointer must fail instead of panicking.
+fn nofault_test() {
+    use ros_sys::memory::nofault;
+
+    let src = 0x0123_4567_89ab_cdef_u64.to_ne_bytes();
+    let mut dst = [0u8; 8];
+
+    nofault::copy_from_nofault(&mut dst, src.as_ptr() as usize).expect("Nofault test: copy failed");
+    assert_eq!(dst, src);
+
+    assert!(nofault::copy_from_nofault(&mut dst, 0).is_err());
+}
+
+/// Run code in user mode. A task exiting through the syscall hands back its exit code, a faulting
+/// task is killed without taking down the kernel.
+fn user_task_test() {
+    use ros_sys::{
+        memory::mmu::{AccessPermissions, AttributeFields, MemAttributes},
+        task::{self, TaskExit, UserTask},
+    };
+
+    const CODE_ADDR: usize = 0x40_0000;
+
+    let code_attributes = AttributeFields {
+        mem_attributes: MemAttributes::CacheableDram,
+        acc_perms: AccessPermissions::UserReadOnly,
+        execute_never: false,
+    };
+
+    let load = |name, code: &[u32]| {
+        let mut user_task = UserTask::new(name).expect("User task test: no task");
+        let bytes: Vec<u8> = code.iter().flat_map(|x| x.to_le_bytes()).collect();
+
+        let address_space = user_task.address_space_mut();
+        address_space
+            .map_new_pages(CODE_ADDR, 1, &code_attributes)
+            .expect("User task test: map failed");
+        address_space
+            .copy_to_user(CODE_ADDR, &bytes)
+            .expect("User task test: copy failed");
+        user_task.set_entry(CODE_ADDR);
+
+        user_task
+    };
+
+    // add x0, x0, #1; mov x8, #SYS_EXIT; svc #0
+    let mut exiting = load(
+        "exit",
+        &[
+            0x9100_0400,
+            0xd280_0008 | ((task::SYS_EXIT as u32) << 5),
+            0xd400_0001,
+        ],
+    );
+    assert_eq!(exiting.run(41), TaskExit::Exited(42));
+
+    // ldr x0, [x1], with x1 zeroed on entry.
+    let mut faulting = load("fault", &[0xf940_0020]);
+    assert_eq!(faulting.run(0), TaskExit::Killed);
+}
+
+/// Load and run an embedded ELF executable. It exits with its argc, plus 40 from its data segment,
+/// plus the zero it reads from its BSS.
+fn elf_loader_test() {
+    use ros_sys::task::{elf, TaskExit};
+
+    // Built from user/exit_argc.S by `make user_fixtures`.
+    static EXIT_ARGC: &[u8] = include_bytes!("user/exit_argc.elf");
+
+    let mut user_task = elf::load("exit_argc", EXIT_ARGC, &["exit_argc", "two"])
+        .expect("ELF loader test: load failed");
+    assert_eq!(user_task.run(0), TaskExit::Exited(42));
+}
+
+/// Run threads next to the main thread. One of them never yields, so the main thread only wakes
+/// up from its sleep if the timer tick preempts it.
+fn scheduler_test() {
+    use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use ros_sys::scheduler::{scheduler, DEFAULT_PRIORITY};
+
+    static STOP: AtomicBool = AtomicBool::new(false);
+    static YIELDS: AtomicUsize = AtomicUsize::new(0);
+    static FINISHED: AtomicUsize = AtomicUsize::new(0);
+
+    scheduler()
+        .spawn("yielder", DEFAULT_PRIORITY, || {
+            while !STOP.load(Ordering::Relaxed) {
+                YIELDS.fetch_add(1, Ordering::Relaxed);
+                scheduler().yield_now();
+            }
+            FINISHED.fetch_add(1, Ordering::Relaxed);
+        })
+        .expect("Scheduler test: spawn failed");
+    scheduler()
+        .spawn("spinner", DEFAULT_PRIORITY, || {
+            while !STOP.load(Ordering::Relaxed) {
+                cpu::nop();
+            }
+            FINISHED.fetch_add(1, Ordering::Relaxed);
+        })
+        .expect("Scheduler test: spawn failed");
+
+    scheduler().sleep(Duration::from_millis(100));
+    scheduler().print_threads();
+    assert!(YIELDS.load(Ordering::Relaxed) > 0);
+
+    STOP.store(true, Ordering::Relaxed);
+    while FINISHED.load(Ordering::Relaxed) < 2 {
+        scheduler().yield_now();
+    }
+}
+
+/// Let timeouts fire while the main thread sleeps. Cancelled timeouts must not fire anymore.
+fn timeout_test() {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use ros_sys::scheduler::scheduler;
+
+    static ONE_SHOT: AtomicUsize = AtomicUsize::new(0);
+    static PERIODIC: AtomicUsize = AtomicUsize::new(0);
+    static CANCELLED: AtomicUsize = AtomicUsize::new(0);
+
+    let timers = timer_manager::timer_manager();
+
+    timers
+        .set_timeout(Duration::from_millis(20), || {
+            ONE_SHOT.fetch_add(1, Ordering::Relaxed);
+        })
+        .expect("Timeout test: set failed");
+    let periodic = timers
+        .set_timeout_periodic(Duration::from_millis(10), || {
+            PERIODIC.fetch_add(1, Ordering::Relaxed);
+        })
+        .expect("Timeout test: set failed");
+    let cancelled = timers
+        .set_timeout(Duration::from_millis(30), || {
+            CANCELLED.fetch_add(1, Ordering::Relaxed);
+        })
+        .expect("Timeout test: set failed");
+    timers
+        .cancel_timeout(cancelled)
+        .expect("Timeout test: cancel failed");
+
+    scheduler().sleep(Duration::from_millis(100));
+    timers
+        .cancel_timeout(periodic)
+        .expect("Timeout test: cancel failed");
+    let periods = PERIODIC.load(Ordering::Relaxed);
+
+    scheduler().sleep(Duration::from_millis(50));
+    assert_eq!(ONE_SHOT.load(Ordering::Relaxed), 1);
+    assert!(periods >= 5);
+    assert_eq!(PERIODIC.load(Ordering::Relaxed), periods);
+    assert_eq!(CANCELLED.load(Ordering::Relaxed), 0);
+    assert!(timers.cancel_timeout(cancelled).is_err());
+}
+
+/// The BCM2711 System Timer and the architectural timer must agree on how much time passes.
+fn system_timer_test() {
+    use ros_sys::timer_manager::interface::Clock;
+
+    let timers = timer_manager::timer_manager();
+    let system_timer = &boards::rpi4::SYSTEM_TIMER;
+    info!("      Timeouts run on: {}", timers.clock().name());
+
+    let (arch_start, system_start) = (timers.uptime(), system_timer.uptime());
+    timers.sleep_for(Duration::from_millis(100));
+    let arch_elapsed = timers.uptime() - arch_start;
+    let system_elapsed = system_timer.uptime() - system_start;
+
+    assert!(arch_elapsed.abs_diff(system_elapsed) < Duration::from_millis(1));
+}
+
+/// Set the wall clock from a date and time string, and let it run over into the next year. Log
+/// lines show UTC from now on.
+fn wall_clock_test() {
+    use ros_sys::timer_manager::datetime::DateTime;
+
+    let timers = timer_manager::timer_manager();
+
+    assert!(DateTime::parse("2025-02-29T00:00:00Z").is_err());
+    assert!(DateTime::parse("2025-12-31 24:00:00").is_err());
+
+    let start = DateTime::parse("2025-12-31T23:59:59Z").expect("Wall clock test: parse failed");
+    assert_eq!(start.to_unix(), Duration::from_secs(1_767_225_599));
+    assert_eq!(DateTime::from_unix(start.to_unix()), Ok(start));
+
+    timers.set_now(&start).expect("Wall clock test: set failed");
+    timers.sleep_for(Duration::from_millis(1100));
+
+    let now = timers.now().expect("Wall clock test: not set");
+    assert_eq!((now.year(), now.month(), now.day()), (2026, 1, 1));
+
+    timers.set_log_utc(true);
+    info!("      Now: {}", now);
+}
+
+/// Stop and restart the watchdog the board started. Timeouts longer than the counter holds are
+/// refused.
+fn watchdog_test() {
+    use drivers::watchdog::bcm2711_watchdog::Bcm2711Watchdog;
+
+    let watchdog = &boards::rpi4::WATCHDOG;
+    assert!(watchdog.is_running());
+
+    watchdog.stop();
+    assert!(!watchdog.is_running());
+
+    assert!(watchdog
+        .start(Bcm2711Watchdog::MAX_TIMEOUT + Duration::from_secs(1))
+        .is_err());
+    assert!(!watchdog.is_running());
+
+    watchdog
+        .start(Bcm2711Watchdog::MAX_TIMEOUT)
+        .expect("Watchdog test: start failed");
+    assert!(watchdog.is_running());
+    watchdog.pet();
+}
+
+/// Take the spinlocks, through closures and through guards. The IRQ-safe ones mask IRQs only while
+/// they are held.
+fn spinlock_test() {
+    use ros_sys::{
+        exception::asynchronous::is_local_irq_masked,
+        synchronization::{
+            interface::{Mutex, ReadWriteEx},
+            IrqSafeRwSpinLock, IrqSafeSpinLock, SpinLock,
+        },
+    };
+
+    let counter = SpinLock::new(0);
+    (0..10).for_each(|_| counter.lock(|x| *x += 1));
+    assert_eq!(counter.lock(|x| *x), 10);
+
+    let irq_safe = IrqSafeSpinLock::new(());
+    assert!(!is_local_irq_masked());
+    irq_safe.lock(|_| assert!(is_local_irq_masked()));
+    assert!(!is_local_irq_masked());
+
+    let rw = IrqSafeRwSpinLock::new(1);
+    assert_eq!(rw.read(|x| *x), 1);
+    rw.write(|x| *x += 1);
+    rw.read(|x| assert!(is_local_irq_masked() && *x == 2));
+    assert!(!is_local_irq_masked());
+
+    // Dropping the inner guard must not unmask IRQs while the outer one is still held.
+    let outer = irq_safe.lock_guard();
+    let mut writer = rw.write_guard();
+    *writer += 1;
+    drop(writer);
+    assert!(is_local_irq_masked());
+    drop(outer);
+    assert!(!is_local_irq_masked());
+
+    let held = irq_safe.lock_guard();
+    assert!(irq_safe.try_lock_guard().is_none() && is_local_irq_masked());
+    drop(held);
+    assert!(irq_safe.try_lock_guard().is_some());
+    assert!(!is_local_irq_masked());
+
+    assert_eq!(*rw.read_guard(), 3);
+    *counter.lock_guard() += 1;
+    assert_eq!(counter.lock(|x| *x), 11);
+}
+
+/// Release the secondary cores from the firmware. All of them must come online, and releasing them
+/// twice is refused.
+fn smp_test() {
+    use ros_sys::{cpu::smp, state};
+
+    unsafe { boards::rpi4::start_secondary_cores() }.expect("SMP test: start failed");
+    assert_eq!(smp::num_cores_online(), smp::NUM_CORES);
+    assert!(state::state_manager().is_multi_core_main());
+
+    assert!(unsafe { boards::rpi4::start_secondary_cores() }.is_err());
+    info!("      Cores online: {}", smp::num_cores_online());
+}
+
+/// Count on the boot core, once through an atomic and once through a `Cell`. Only the boot core's
+/// counts change, and IRQs are masked while they do.
+fn percpu_test() {
+    use core::{
+        cell::Cell,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+    use ros_sys::{
+        cpu::{percpu::PerCpu, smp, BOOT_CORE_ID},
+        exception::asynchronous::is_local_irq_masked,
+    };
+
+    static COUNTS: PerCpu<AtomicUsize> =
+        PerCpu::from_array([const { AtomicUsize::new(0) }; smp::NUM_CORES]);
+    let cells = PerCpu::from_array([const { Cell::new(0) }; smp::NUM_CORES]);
+
+    for _ in 0..3 {
+        COUNTS.with(|x| x.fetch_add(1, Ordering::Relaxed));
+        cells.with(|x| {
+            assert!(is_local_irq_masked());
+            x.set(x.get() + 1)
+        });
+    }
+    assert!(!is_local_irq_masked());
+    assert_eq!(cells.with(|x| x.get()), 3);
+
+    for (core_id, count) in COUNTS.iter().enumerate() {
+        let count = count.load(Ordering::Relaxed);
+        let expected = match core_id == BOOT_CORE_ID as usize {
+            true => 3,
+            false => 0,
+        };
+
+        assert_eq!(count, expected);
+        info!("      Core {}: {}", core_id, count);
+    }
+}
+
+/// Take two locks in both orders. Lockdep must report the second order, and nothing before it.
+/// Validation stops with the report, so this runs after the other tests.
+#[cfg(feature = "lockdep")]
+fn lockdep_test() {
+    use ros_sys::synchronization::{lockdep, SpinLock};
+
+    assert!(!lockdep::reported(), "Lockdep test: reported earlier");
+
+    let a = SpinLock::new(());
+    let b = SpinLock::new(());
+    {
+        let _a = a.lock_guard();
+        let _b = b.lock_guard();
+    }
+    assert!(!lockdep::reported());
+
+    info!("      A lock order inversion report follows");
+    {
+        let _b = b.lock_guard();
+        let _a = a.lock_guard();
+    }
+    assert!(lockdep::reported());
+}
+
+/// Written by `watchdog_reset_test()` before the watchdog bites. Lives in `.noinit`, so it survives
+/// resets that keep the RAM powered. Unlike the reset reason, this includes resets on QEMU, which
+/// resets the PM block along with the SoC. Holds whatever the RAM held on other boots.
+#[cfg(feature = "watchdog_reset_test")]
+#[link_section = ".noinit"]
+static mut WATCHDOG_RESET_TEST_MARKER: core::mem::MaybeUninit<u64> =
+    core::mem::MaybeUninit::uninit();
+
+/// The marker value of a pending watchdog reset test.
+#[cfg(feature = "watchdog_reset_test")]
+const WATCHDOG_RESET_TEST_MAGIC: u64 = 0x5741_5443_4844_4f47;
+
+/// Stop petting the watchdog and wait for it to reset the board. On the boot after, the test
+/// passes instead, so the board doesn't reset over and over.
+#[cfg(feature = "watchdog_reset_test")]
+fn watchdog_reset_test() {
+    use core::ptr::{addr_of_mut, read_volatile, write_volatile};
+    use drivers::watchdog::bcm2711_watchdog::{Bcm2711Watchdog, ResetReason};
+
+    let watchdog = &boards::rpi4::WATCHDOG;
+    let marker = addr_of_mut!(WATCHDOG_RESET_TEST_MARKER) as *mut u64;
+
+    // The reset drops the caches, so the marker must be in memory by then.
+    let set_marker = |value| unsafe {
+        write_volatile(marker, value);
+        cpu::clean_invalidate_data_cache(marker as usize, core::mem::size_of::<u64>());
+    };
+
+    let pending = unsafe { read_volatile(marker) } == WATCHDOG_RESET_TEST_MAGIC;
+    if pending || watchdog.reset_reason() == ResetReason::Watchdog {
+        set_marker(0);
+        info!("Watchdog reset test OK, the watchdog reset the board");
+        return;
+    }
+
+    info!("Watchdog reset test, the board must reset");
+    set_marker(WATCHDOG_RESET_TEST_MAGIC);
+    watchdog
+        .start(Bcm2711Watchdog::MAX_TIMEOUT)
+        .expect("Watchdog reset test: start failed");
+
+    exception::asynchronous::local_irq_mask();
+    cpu::wait_forever()
+}
+
 #[no_mangle]
 fn os_early_entry() -> ! {
     info!(
@@ -32,9 +487,14 @@ fn os_early_entry() -> ! {
         env!("CARGO_PKG_VERSION")
     );
     info!("Booting on: {}", board::board().board_name());
+    info!("Last reset: {}", boards::rpi4::WATCHDOG.reset_reason());
+
+    if cfg!(feature = "panic_reboot") {
+        ros_sys::panic::set_reboot_delay(Some(PANIC_REBOOT_DELAY));
+    }
 
     info!("MMU online. Special regions:");
-    boards::rpi4::memory::mmu::virt_mem_layout().print_layout();
+    ros_sys::memory::mmu::virt_mem_layout().print_layout();
 
     let (_, privilege_level) = exception::current_privilege_level();
     info!("Current privilege level: {}", privilege_level);
@@ -55,10 +515,94 @@ fn os_early_entry() -> ! {
     info!("Registered IRQ handlers:");
     exception::asynchronous::irq_manager().print_handler();
 
+    info!("Registered syscalls:");
+    exception::syscall::syscall_table().print_syscalls();
+
+    info!("Heap test");
+    let squares: Vec<usize> = (0..64).map(|x| x * x).collect();
+    info!(
+        "Heap test OK, sum of squares: {}",
+        squares.iter().sum::<usize>()
+    );
+    drop(squares);
+
+    info!("Kernel heap:");
+    heap_alloc::kernel_heap_allocator().print_usage();
+
+    info!("Physical frames:");
+    frame_alloc::frame_allocator().print_usage();
+
+    info!("Frame allocator test");
+    frame_alloc_test();
+    info!("Frame allocator test OK");
+
+    info!("MMU remap test");
+    mmu_remap_test();
+    info!("MMU remap test OK");
+
+    info!("Nofault test");
+    nofault_test();
+    info!("Nofault test OK");
+
+    info!("User task test");
+    user_task_test();
+    info!("User task test OK");
+
+    info!("ELF loader test");
+    elf_loader_test();
+    info!("ELF loader test OK");
+
+    info!("Scheduler test");
+    scheduler_test();
+    info!("Scheduler test OK");
+
+    info!("Timeout test");
+    timeout_test();
+    info!("Timeout test OK");
+
+    info!("System timer test");
+    system_timer_test();
+    info!("System timer test OK");
+
+    info!("Wall clock test");
+    wall_clock_test();
+    info!("Wall clock test OK");
+
+    if cfg!(feature = "watchdog") {
+        info!("Watchdog test");
+        watchdog_test();
+        info!("Watchdog test OK");
+    }
+
+    info!("Spinlock test");
+    spinlock_test();
+    info!("Spinlock test OK");
+
+    info!("SMP test");
+    smp_test();
+    info!("SMP test OK");
+
+    info!("Per-CPU test");
+    percpu_test();
+    info!("Per-CPU test OK");
+
     info!("Timer test, 1s");
-    timer_manager::timer_manager().spin_for(Duration::from_secs(1));
+    let timers = timer_manager::timer_manager();
+    let start = timers.uptime();
+    timers.sleep_for(Duration::from_secs(1));
+    assert!(timers.uptime() - start >= Duration::from_secs(1));
     info!("Timer test OK");
 
+    #[cfg(feature = "lockdep")]
+    {
+        info!("Lockdep test");
+        lockdep_test();
+        info!("Lockdep test OK");
+    }
+
+    #[cfg(feature = "watchdog_reset_test")]
+    watchdog_reset_test();
+
     info!("Echoing input now");
     cpu::wait_forever()
 }
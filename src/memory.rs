@@ -1,3 +0,0 @@
-//! Memory Management.
-
-pub mod mmu;
@@ -1,4 +1,4 @@
-use core::arch::global_asm;
+use core::{arch::global_asm, sync::atomic::AtomicU64};
 
 use aarch64_cpu::{
     asm,
@@ -6,19 +6,45 @@ use aarch64_cpu::{
 };
 use tock_registers::interfaces::Writeable;
 
+use crate::{
+    cpu::smp::NUM_CORES,
+    memory::{self, mmu::boot},
+};
+
 global_asm!(
     include_str!("boot.S"),
     CONST_CURRENTEL_EL2 = const 0x8,
-    CONST_CORE_ID_MASK = const 0b11
+    CONST_CORE_ID_MASK = const 0b11,
+    CONST_MAIR_EL1 = const boot::MAIR_EL1_VALUE,
+    CONST_TCR_EL1 = const boot::TCR_EL1_VALUE,
+    CONST_SCTLR_EL1_ENABLE = const boot::SCTLR_EL1_ENABLE,
+    CONST_KERNEL_VIRT_OFFSET = const memory::KERNEL_VIRT_OFFSET,
+    BOOT_TRANSLATION_TABLE = sym boot::BOOT_TRANSLATION_TABLE,
+    RPI_OS_INIT = sym crate::rpi_os_init,
+    RPI_OS_SECONDARY_INIT = sym crate::rpi_os_secondary_init
 );
 
+extern "C" {
+    /// The entry the secondary cores are released to.
+    pub(crate) fn _start_secondary();
+}
+
 #[unsafe(no_mangle)]
 #[unsafe(link_section = ".text._start_arguments")]
 pub static BOOT_CORE_ID: u64 = 0;
 
+/// The physical stack end address of each secondary core, read by `_start_secondary` before the
+/// MMU is on. Must be written back to memory before the core is released.
+#[unsafe(no_mangle)]
+pub(crate) static SECONDARY_CORE_STACKS: [AtomicU64; NUM_CORES] =
+    [const { AtomicU64::new(0) }; NUM_CORES];
+
 /// Prepares the transition from EL2 to EL1.
 #[inline(always)]
-unsafe fn prepare_el2_to_el1_transition(phys_boot_core_stack_end_exclusive_addr: u64) {
+unsafe fn prepare_el2_to_el1_transition(
+    phys_stack_end_exclusive_addr: u64,
+    phys_el1_entry_addr: u64,
+) {
     // Enable timer counter registers for EL1.
     CNTHCTL_EL2.write(CNTHCTL_EL2::EL1PCEN::SET + CNTHCTL_EL2::EL1PCTEN::SET);
 
@@ -39,18 +65,25 @@ unsafe fn prepare_el2_to_el1_transition(phys_boot_core_stack_end_exclusive_addr:
             + SPSR_EL2::M::EL1h,
     );
 
-    // Second, let the link register point to kernel_init().
-    ELR_EL2.set(crate::rpi_os_init as *const () as u64);
+    // Second, let the link register point to the EL1 entry. The MMU is still off, so it must be a
+    // physical address.
+    ELR_EL2.set(phys_el1_entry_addr);
 
     // Set up SP_EL1 (stack pointer), which will be used by EL1 once we "return" to it. Since there
     // are no plans to ever return to EL2, just re-use the same stack.
-    SP_EL1.set(phys_boot_core_stack_end_exclusive_addr);
+    SP_EL1.set(phys_stack_end_exclusive_addr);
 }
 
-/// The Rust entry of the `kernel` binary.
+/// The Rust entry of the `kernel` binary, on the boot core as well as on the secondary cores.
+///
+/// Runs from physical addresses, before the MMU is on. Must not touch anything that is addressed
+/// absolutely.
 #[unsafe(no_mangle)]
-pub unsafe extern "C" fn _rust_start(phys_boot_core_stack_end_exclusive_addr: u64) -> ! {
-    prepare_el2_to_el1_transition(phys_boot_core_stack_end_exclusive_addr);
+pub unsafe extern "C" fn _rust_start(
+    phys_stack_end_exclusive_addr: u64,
+    phys_el1_entry_addr: u64,
+) -> ! {
+    prepare_el2_to_el1_transition(phys_stack_end_exclusive_addr, phys_el1_entry_addr);
 
     asm::eret()
 }
@@ -1,8 +1,10 @@
 //! Architechural processor code.
 
-use aarch64_cpu::asm;
+use core::arch::asm;
 
-pub use asm::nop;
+use aarch64_cpu::asm::{self, barrier};
+
+pub use asm::{nop, sev, wfi};
 
 #[inline(always)]
 pub fn wait_forever() -> ! {
@@ -10,3 +12,40 @@ pub fn wait_forever() -> ! {
         asm::wfe();
     }
 }
+
+/// Make `size` bytes of code written through the data cache starting at `virt_addr` visible to
+/// instruction fetches on all cores.
+pub fn sync_instruction_cache(virt_addr: usize, size: usize) {
+    let ctr: usize;
+    unsafe { asm!("mrs {ctr}, CTR_EL0", ctr = out(reg) ctr, options(nomem, nostack)) };
+
+    // CTR_EL0.DminLine, bits [19:16], is log2 of the smallest data cache line in words.
+    let line_size = 4 << ((ctr >> 16) & 0xf);
+    let mut addr = virt_addr & !(line_size - 1);
+
+    while addr < virt_addr + size {
+        unsafe { asm!("dc cvau, {addr}", addr = in(reg) addr, options(nostack)) };
+        addr += line_size;
+    }
+    barrier::dsb(barrier::ISH);
+
+    unsafe { asm!("ic ialluis", options(nostack)) };
+    barrier::dsb(barrier::ISH);
+    barrier::isb(barrier::SY);
+}
+
+/// Write `size` bytes starting at `virt_addr` back to memory and drop them from the data caches,
+/// for observers that access memory with caching off, like a core that has no MMU on yet.
+pub fn clean_invalidate_data_cache(virt_addr: usize, size: usize) {
+    let ctr: usize;
+    unsafe { asm!("mrs {ctr}, CTR_EL0", ctr = out(reg) ctr, options(nomem, nostack)) };
+
+    let line_size = 4 << ((ctr >> 16) & 0xf);
+    let mut addr = virt_addr & !(line_size - 1);
+
+    while addr < virt_addr + size {
+        unsafe { asm!("dc civac, {addr}", addr = in(reg) addr, options(nostack)) };
+        addr += line_size;
+    }
+    barrier::dsb(barrier::SY);
+}
@@ -0,0 +1,16 @@
+//! Architectural per-core data.
+
+use aarch64_cpu::registers::TPIDR_EL1;
+use tock_registers::interfaces::{Readable, Writeable};
+
+/// Point the executing core at its block of per-core data.
+#[inline(always)]
+pub fn set_local_block(addr: usize) {
+    TPIDR_EL1.set(addr as u64);
+}
+
+/// Return the address of the executing core's block of per-core data.
+#[inline(always)]
+pub fn local_block() -> usize {
+    TPIDR_EL1.get() as usize
+}
@@ -3,13 +3,16 @@
 use aarch64_cpu::registers::MPIDR_EL1;
 use tock_registers::interfaces::Readable;
 
+/// The number of cores `core_id()` tells apart.
+pub const NUM_CORES: usize = 4;
+
 /// Return the executing core's id.
 #[inline(always)]
 pub fn core_id<T>() -> T
 where
     T: From<u8>,
 {
-    const CORE_MASK: u64 = 0b11;
+    const CORE_MASK: u64 = NUM_CORES as u64 - 1;
 
     T::from((MPIDR_EL1.get() & CORE_MASK) as u8)
 }
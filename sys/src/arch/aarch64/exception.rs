@@ -1,6 +1,10 @@
 //! Architectural synchronous and asynchronous exception handling.
 
-use core::{arch::global_asm, cell::UnsafeCell, fmt};
+use core::{
+    arch::{asm, global_asm},
+    cell::UnsafeCell,
+    fmt, mem,
+};
 
 use aarch64_cpu::{
     asm::barrier,
@@ -8,13 +12,56 @@ use aarch64_cpu::{
 };
 use tock_registers::{
     interfaces::{Readable, Writeable},
+    register_bitfields,
     registers::InMemoryRegister,
 };
 
-use crate::exception::{self, PrivilegeLevel};
+use crate::{
+    exception::{
+        self,
+        fixup::fixup_manager,
+        syscall::{syscall_table, SyscallArgs},
+        PrivilegeLevel,
+    },
+    memory::{self, mmu, KERNEL_VIRT_OFFSET},
+    scheduler,
+    task::{self, TaskExit},
+    warn,
+};
 
 // Assembly counterpart to this file.
-global_asm!(include_str!("exception.S"));
+global_asm!(
+    include_str!("exception.S"),
+    CONST_KERNEL_STACK_SHIFT = const memory::KERNEL_STACK_SHIFT,
+    CONST_OVERFLOW_STACK_SIZE = const OVERFLOW_STACK_SIZE,
+    OVERFLOW_STACK = sym OVERFLOW_STACK
+);
+
+const OVERFLOW_STACK_SIZE: usize = 64 * 1024;
+
+/// The stack a kernel stack overflow is reported on. Only ever used by the exception entry code.
+#[allow(dead_code)]
+#[repr(align(16))]
+struct OverflowStack(UnsafeCell<[u8; OVERFLOW_STACK_SIZE]>);
+
+unsafe impl Sync for OverflowStack {}
+
+static OVERFLOW_STACK: OverflowStack = OverflowStack(UnsafeCell::new([0; OVERFLOW_STACK_SIZE]));
+
+// The instruction specific syndrome of data and instruction aborts.
+register_bitfields! {
+    u64,
+    ISS_ABORT [
+        /// FAR not valid.
+        FNV OFFSET(10) NUMBITS(1) [],
+
+        /// Write not Read. Only valid for data aborts.
+        WNR OFFSET(6) NUMBITS(1) [],
+
+        /// Data or instruction fault status code.
+        FSC OFFSET(0) NUMBITS(6) [],
+    ]
+}
 
 /// Wrapper structs for memory copies of registers.
 #[repr(transparent)]
@@ -117,6 +164,10 @@ impl fmt::Display for EsrEl1 {
         // Exception class.
         let ec_translation = match self.exception_class() {
             Some(ESR_EL1::EC::Value::DataAbortCurrentEL) => "Data Abort, current EL",
+            Some(ESR_EL1::EC::Value::InstrAbortCurrentEL) => "Instruction Abort, current EL",
+            Some(ESR_EL1::EC::Value::DataAbortLowerEL) => "Data Abort, lower EL",
+            Some(ESR_EL1::EC::Value::InstrAbortLowerEL) => "Instruction Abort, lower EL",
+            Some(ESR_EL1::EC::Value::SVC64) => "Supervisor Call, AArch64",
             _ => "N/A",
         };
         writeln!(f, " - {}", ec_translation)?;
@@ -130,6 +181,112 @@ impl fmt::Display for EsrEl1 {
     }
 }
 
+/// The fault status codes of data and instruction aborts. Levels are translation table levels.
+#[derive(Copy, Clone)]
+enum FaultStatus {
+    AddressSize(u64),
+    Translation(u64),
+    AccessFlag(u64),
+    Permission(u64),
+    SyncExternal,
+    Alignment,
+    TlbConflict,
+    Other(u64),
+}
+
+impl From<u64> for FaultStatus {
+    fn from(fsc: u64) -> Self {
+        let level = fsc & 0b11;
+
+        match fsc {
+            0b00_0000..=0b00_0011 => FaultStatus::AddressSize(level),
+            0b00_0100..=0b00_0111 => FaultStatus::Translation(level),
+            0b00_1000..=0b00_1011 => FaultStatus::AccessFlag(level),
+            0b00_1100..=0b00_1111 => FaultStatus::Permission(level),
+            0b01_0000 => FaultStatus::SyncExternal,
+            0b10_0001 => FaultStatus::Alignment,
+            0b11_0000 => FaultStatus::TlbConflict,
+            x => FaultStatus::Other(x),
+        }
+    }
+}
+
+impl fmt::Display for FaultStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FaultStatus::AddressSize(l) => write!(f, "Address size fault, level {}", l),
+            FaultStatus::Translation(l) => write!(f, "Translation fault, level {}", l),
+            FaultStatus::AccessFlag(l) => write!(f, "Access flag fault, level {}", l),
+            FaultStatus::Permission(l) => write!(f, "Permission fault, level {}", l),
+            FaultStatus::SyncExternal => write!(f, "Synchronous external abort"),
+            FaultStatus::Alignment => write!(f, "Alignment fault"),
+            FaultStatus::TlbConflict => write!(f, "TLB conflict abort"),
+            FaultStatus::Other(x) => write!(f, "Fault status {:#x}", x),
+        }
+    }
+}
+
+/// A decoded data or instruction abort.
+struct Abort {
+    instruction_fetch: bool,
+    write: bool,
+    status: FaultStatus,
+    fault_addr: Option<usize>,
+}
+
+impl Abort {
+    /// Decode the abort of an exception context. Returns `None` for other exception classes.
+    fn decode(e: &ExceptionContext) -> Option<Self> {
+        use ESR_EL1::EC::Value::*;
+
+        let instruction_fetch = match e.exception_class()? {
+            InstrAbortLowerEL | InstrAbortCurrentEL => true,
+            DataAbortLowerEL | DataAbortCurrentEL => false,
+            _ => return None,
+        };
+
+        let iss = InMemoryRegister::<u64, ISS_ABORT::Register>::new(e.esr_el1.0.read(ESR_EL1::ISS));
+        let fault_addr = match iss.is_set(ISS_ABORT::FNV) {
+            true => None,
+            false => Some(FAR_EL1.get() as usize),
+        };
+
+        Some(Self {
+            instruction_fetch,
+            write: !instruction_fetch && iss.is_set(ISS_ABORT::WNR),
+            status: FaultStatus::from(iss.read(ISS_ABORT::FSC)),
+            fault_addr,
+        })
+    }
+}
+
+impl fmt::Display for Abort {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let access = match (self.instruction_fetch, self.write) {
+            (true, _) => "instruction fetch",
+            (false, true) => "write",
+            (false, false) => "read",
+        };
+
+        write!(f, "{} on {}", self.status, access)?;
+
+        let Some(addr) = self.fault_addr else {
+            return write!(f, " of an unknown address");
+        };
+
+        write!(f, " of {:#018x}", addr)?;
+
+        if addr < KERNEL_VIRT_OFFSET {
+            return write!(f, " in the user address space");
+        }
+
+        match mmu::virt_mem_layout().find_descriptor(addr) {
+            Some(descriptor) => write!(f, " in \"{}\"", descriptor.name),
+            None => write!(f, " outside of the kernel's layout descriptors"),
+        }
+    }
+}
+
 /// The exception context as it is stored on the stack on exception entry.
 #[repr(C)]
 struct ExceptionContext {
@@ -147,14 +304,32 @@ struct ExceptionContext {
 
     /// Exception syndrome register.
     esr_el1: EsrEl1,
+
+    /// The stack pointer of EL0.
+    sp_el0: u64,
+
+    /// Keeps the size a multiple of 16 bytes, as the stack pointer demands.
+    _reserved: u64,
 }
 
+// The assembly code relies on the layout.
+const _: () = assert!(mem::size_of::<ExceptionContext>() == 16 * 18);
+
 impl ExceptionContext {
     #[inline(always)]
     fn exception_class(&self) -> Option<ESR_EL1::EC::Value> {
         self.esr_el1.exception_class()
     }
 
+    /// Dispatch a syscall. The caller passes the number in x8 and the arguments in x0 to x5, the
+    /// return value replaces x0. ELR_EL1 already points past the SVC instruction.
+    fn handle_syscall(&mut self) {
+        let mut args: SyscallArgs = [0; 6];
+        args.copy_from_slice(&self.gpr[0..6]);
+
+        self.gpr[0] = syscall_table().dispatch(self.gpr[8], &args);
+    }
+
     #[inline(always)]
     fn fault_address_valid(&self) -> bool {
         use ESR_EL1::EC::Value::*;
@@ -185,6 +360,7 @@ impl fmt::Display for ExceptionContext {
 
         writeln!(f, "{}", self.spsr_el1)?;
         writeln!(f, "ELR_EL1: {:#018x}", self.elr_el1)?;
+        writeln!(f, "SP_EL0:  {:#018x}", self.sp_el0)?;
         writeln!(f)?;
         writeln!(f, "General purpose register:")?;
 
@@ -208,6 +384,20 @@ fn default_exception_handler(exc: &ExceptionContext) {
     panic!("CPU Exception!\n{}", exc);
 }
 
+/// Kill the user task that caused an exception the kernel can't resolve for it.
+fn kill_user_task(e: &ExceptionContext) -> ! {
+    match Abort::decode(e) {
+        Some(abort) => {
+            warn!("User page fault: {}\n{}", abort, e);
+        }
+        None => {
+            warn!("User exception\n{}", e);
+        }
+    }
+
+    task::exit_current_task(TaskExit::Killed)
+}
+
 // Current, EL0
 
 #[no_mangle]
@@ -229,17 +419,23 @@ extern "C" fn current_el0_serror(_e: &mut ExceptionContext) {
 
 #[no_mangle]
 extern "C" fn current_elx_synchronous(e: &mut ExceptionContext) {
-    if e.fault_address_valid() {
-        let far_el1 = FAR_EL1.get();
+    // Threads yield through SVC, see `yield_to_scheduler()`. Returning is all it takes.
+    if let Some(ESR_EL1::EC::Value::SVC64) = e.exception_class() {
+        return;
+    }
 
-        if far_el1 == 8 * 1024 * 1024 * 1024 {
-            e.elr_el1 += 4;
+    if let Some(abort) = Abort::decode(e) {
+        // Faults of instructions that expect them resume at their fixup.
+        if let Some(fixup) = fixup_manager().search(e.elr_el1 as usize) {
+            e.elr_el1 = fixup.fixup_addr() as u64;
 
             return;
         }
 
-        default_exception_handler(e);
+        panic!("Kernel page fault: {}\n{}", abort, e);
     }
+
+    default_exception_handler(e);
 }
 
 #[no_mangle]
@@ -257,17 +453,24 @@ extern "C" fn current_elx_serror(e: &mut ExceptionContext) {
 
 #[no_mangle]
 extern "C" fn lower_aarch64_synchronous(e: &mut ExceptionContext) {
-    default_exception_handler(e);
+    if let Some(ESR_EL1::EC::Value::SVC64) = e.exception_class() {
+        e.handle_syscall();
+
+        return;
+    }
+
+    kill_user_task(e);
 }
 
 #[no_mangle]
-extern "C" fn lower_aarch64_irq(e: &mut ExceptionContext) {
-    default_exception_handler(e);
+extern "C" fn lower_aarch64_irq(_e: &mut ExceptionContext) {
+    let token = unsafe { &exception::asynchronous::IrqContext::new() };
+    exception::asynchronous::irq_manager().handle_pending_irqs(token);
 }
 
 #[no_mangle]
 extern "C" fn lower_aarch64_serror(e: &mut ExceptionContext) {
-    default_exception_handler(e);
+    kill_user_task(e);
 }
 
 // Lower, AArch32
@@ -287,6 +490,64 @@ extern "C" fn lower_aarch32_serror(e: &mut ExceptionContext) {
     default_exception_handler(e);
 }
 
+/// Called before every exception return, with the context about to be restored. Returns the
+/// context to restore instead, which belongs to another thread if the scheduler switched.
+#[no_mangle]
+extern "C" fn exception_next_context(e: &mut ExceptionContext) -> *mut ExceptionContext {
+    // Only code running with IRQs unmasked may be preempted. This leaves out exception handlers
+    // and critical sections.
+    if e.spsr_el1.0.is_set(SPSR_EL1::I) {
+        return e;
+    }
+
+    scheduler::scheduler().switch_context(e as *mut ExceptionContext as usize)
+        as *mut ExceptionContext
+}
+
+/// Prepare the context a new thread starts from, right below `stack_top`, and return its address.
+/// Once restored, it calls `entry(arg)` in EL1 with IRQs unmasked, on the rest of the stack.
+///
+/// # Safety
+///
+/// - `stack_top` must be the 16 byte aligned end of an unused kernel stack.
+pub unsafe fn prepare_thread_context(
+    stack_top: usize,
+    entry: extern "C" fn(usize) -> !,
+    arg: usize,
+) -> usize {
+    let context = (stack_top - mem::size_of::<ExceptionContext>()) as *mut ExceptionContext;
+
+    let spsr_el1 = InMemoryRegister::new(0);
+    spsr_el1.write(
+        SPSR_EL1::D::Masked
+            + SPSR_EL1::A::Masked
+            + SPSR_EL1::I::Unmasked
+            + SPSR_EL1::F::Masked
+            + SPSR_EL1::M::EL1h,
+    );
+
+    let mut gpr = [0; 30];
+    gpr[0] = arg as u64;
+
+    context.write(ExceptionContext {
+        gpr,
+        lr: 0,
+        elr_el1: entry as usize as u64,
+        spsr_el1: SpsrEl1(spsr_el1),
+        esr_el1: EsrEl1(InMemoryRegister::new(0)),
+        sp_el0: 0,
+        _reserved: 0,
+    });
+
+    context as usize
+}
+
+/// Take a detour through the exception return path, where the scheduler may switch threads.
+#[inline(always)]
+pub fn yield_to_scheduler() {
+    unsafe { asm!("svc #0", options(nostack)) };
+}
+
 /// The processing element's current privilege level.
 pub fn current_privilege_level() -> (PrivilegeLevel, &'static str) {
     let el = CurrentEL.read_as_enum(CurrentEL::EL);
@@ -298,7 +559,8 @@ pub fn current_privilege_level() -> (PrivilegeLevel, &'static str) {
     }
 }
 
-/// Init exception handling by setting the exception vector base address register.
+/// Init exception handling by setting the exception vector base address register. Every core
+/// calls this for itself.
 /// # Safety
 pub unsafe fn handling_init() {
     extern "Rust" {
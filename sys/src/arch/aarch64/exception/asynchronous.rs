@@ -51,7 +51,7 @@ where
 
 /// Returns whether IRQs are masked on the executing core.
 pub fn is_local_irq_masked() -> bool {
-    !is_masked::<Irq>()
+    is_masked::<Irq>()
 }
 
 /// Unmask IRQs on the executing core.
@@ -0,0 +1,336 @@
+//! Memory Management Unit Driver.
+
+use core::{
+    arch::{asm, global_asm},
+    mem,
+};
+
+use aarch64_cpu::{
+    asm::barrier,
+    registers::{ID_AA64MMFR0_EL1, MAIR_EL1, SCTLR_EL1, TCR_EL1, TTBR0_EL1, TTBR1_EL1},
+};
+use tock_registers::interfaces::{ReadWriteable, Readable, Writeable};
+
+use crate::{
+    memory::{
+        mmu::{
+            self as generic_mmu,
+            translation_table::{self, BootTranslationTable, KernelTranslationTable},
+            AttributeFields, MmuEnableError, TranslationGranule,
+        },
+        virt_to_phys, KERNEL_VIRT_OFFSET,
+    },
+    synchronization::{interface::Mutex, IrqSafeNullLock},
+};
+
+global_asm!(
+    include_str!("mmu.S"),
+    CONST_TCR_EL1_EPD1 = const TCR_EL1::EPD1::DisableTTBR1Walks.value
+);
+
+extern "C" {
+    fn __mmu_replace_ttbr1(phys_ttbr1_baddr: u64);
+}
+
+struct MemoryManagementUnit;
+
+/// The size of the TTBR1 range. It ends at the top of the address space, so it has to start at
+/// the kernel's virtual offset.
+const TTBR1_RANGE_SIZE: usize = 0usize.wrapping_sub(KERNEL_VIRT_OFFSET);
+
+/// The size of the TTBR0 range user address spaces live in. TCR_EL1 gives it the size of the
+/// TTBR1 range, and the user translation tables are walked like the kernel ones.
+pub const USER_ADDR_SPACE_SIZE: usize = {
+    assert!(TTBR1_RANGE_SIZE == translation_table::MAX_ADDR_SPACE_SIZE);
+
+    TTBR1_RANGE_SIZE
+};
+
+/// TCR_EL1.AS is left at 8 bit ASIDs, which every ARMv8 core supports.
+pub const NUM_ASIDS: usize = 256;
+
+/// The translation granule of the kernel. The 64 KiB granule is the default, boards select the
+/// 4 KiB one through the `granule_4k` feature.
+#[cfg(not(feature = "granule_4k"))]
+pub type KernelGranule = TranslationGranule<{ 64 * 1024 }>;
+
+/// The translation granule of the kernel. The 64 KiB granule is the default, boards select the
+/// 4 KiB one through the `granule_4k` feature.
+#[cfg(feature = "granule_4k")]
+pub type KernelGranule = TranslationGranule<{ 4 * 1024 }>;
+
+#[cfg(not(feature = "granule_4k"))]
+const TCR_EL1_GRANULE: u64 = TCR_EL1::TG0::KiB_64.value | TCR_EL1::TG1::KiB_64.value;
+
+#[cfg(feature = "granule_4k")]
+const TCR_EL1_GRANULE: u64 = TCR_EL1::TG0::KiB_4.value | TCR_EL1::TG1::KiB_4.value;
+
+/// TCR_EL1 settings shared by the boot and the kernel translation tables. TTBR0 and TTBR1 both
+/// span 4 GiB, which the boot translation table covers in a single level.
+const TCR_EL1_COMMON: u64 = TCR_EL1::TBI0::Used.value
+    | TCR_EL1::IPS::Bits_40.value
+    | TCR_EL1_GRANULE
+    | TCR_EL1::SH0::Inner.value
+    | TCR_EL1::ORGN0::WriteBack_ReadAlloc_WriteAlloc_Cacheable.value
+    | TCR_EL1::IRGN0::WriteBack_ReadAlloc_WriteAlloc_Cacheable.value
+    | TCR_EL1::T0SZ
+        .val((64 - TTBR1_RANGE_SIZE.trailing_zeros()) as u64)
+        .value
+    | TCR_EL1::SH1::Inner.value
+    | TCR_EL1::ORGN1::WriteBack_ReadAlloc_WriteAlloc_Cacheable.value
+    | TCR_EL1::IRGN1::WriteBack_ReadAlloc_WriteAlloc_Cacheable.value
+    | TCR_EL1::T1SZ
+        .val((64 - TTBR1_RANGE_SIZE.trailing_zeros()) as u64)
+        .value
+    | TCR_EL1::EPD1::EnableTTBR1Walks.value
+    | TCR_EL1::A1::TTBR0.value;
+
+/// Register values the boot code enables the MMU with.
+pub mod boot {
+    use super::*;
+
+    /// The memory types being mapped, see `mair`. The kernel tables use the same.
+    pub const MAIR_EL1_VALUE: u64 =
+        MAIR_EL1::Attr1_Normal_Outer::WriteBack_NonTransient_ReadWriteAlloc.value
+            | MAIR_EL1::Attr1_Normal_Inner::WriteBack_NonTransient_ReadWriteAlloc.value
+            | MAIR_EL1::Attr0_Device::nonGathering_nonReordering_EarlyWriteAck.value;
+
+    /// The identity mapping in TTBR0 stays enabled until the kernel tables are installed.
+    pub const TCR_EL1_VALUE: u64 = TCR_EL1_COMMON | TCR_EL1::EPD0::EnableTTBR0Walks.value;
+
+    /// The bits to set in SCTLR_EL1 for turning on the MMU and caching.
+    pub const SCTLR_EL1_ENABLE: u64 =
+        SCTLR_EL1::M::Enable.value | SCTLR_EL1::C::Cacheable.value | SCTLR_EL1::I::Cacheable.value;
+
+    pub static BOOT_TRANSLATION_TABLE: BootTranslationTable = BootTranslationTable::new();
+}
+
+impl MemoryManagementUnit {
+    /// Setup function for the MAIR_EL1 register.
+    fn set_up_mair(&self) {
+        MAIR_EL1.set(boot::MAIR_EL1_VALUE);
+    }
+
+    /// Configure various settings of stage 1 of the EL1 translation regime. The kernel only uses
+    /// TTBR1, so TTBR0 walks are disabled while no user address space is active. Among others,
+    /// this makes null pointer dereferences fault.
+    fn configure_translation_control(&self) {
+        TCR_EL1.set(TCR_EL1_COMMON | TCR_EL1::EPD0::DisableTTBR0Walks.value);
+    }
+
+    /// Invalidate all EL1 TLB entries of the local core.
+    #[inline(always)]
+    fn tlb_invalidate_all(&self) {
+        unsafe { asm!("tlbi vmalle1", options(nostack, preserves_flags)) };
+        barrier::dsb(barrier::NSH);
+        barrier::isb(barrier::SY);
+    }
+
+    /// Switch TTBR1 to the table at the given physical address.
+    ///
+    /// # Safety
+    ///
+    /// - The new table must map the kernel at the same addresses as the old one.
+    unsafe fn replace_ttbr1(&self, phys_ttbr1_baddr: u64) {
+        // The helper must run while TTBR1 is off, so call it through its identity mapped
+        // physical address.
+        let phys_replace_ttbr1: unsafe extern "C" fn(u64) =
+            mem::transmute(virt_to_phys(__mmu_replace_ttbr1 as *const () as usize));
+
+        phys_replace_ttbr1(phys_ttbr1_baddr);
+    }
+}
+
+/// The kernel translation tables. They stay live after the MMU is enabled, so every change goes
+/// through the lock.
+static KERNEL_TABLES: IrqSafeNullLock<KernelTranslationTable> =
+    IrqSafeNullLock::new(KernelTranslationTable::new());
+
+impl generic_mmu::interface::Mmu for MemoryManagementUnit {
+    unsafe fn enable_mmu_and_caching(&self) -> Result<(), MmuEnableError> {
+        // The boot code turned on the MMU with the boot translation table.
+        if !self.is_enabled() {
+            // unlikely
+            return Err(MmuEnableError::Other("MMU not enabled by the boot code"));
+        }
+
+        // Fail early if translation granule is not supported.
+        if !granule_supported() {
+            // unlikely
+            return Err(MmuEnableError::Other(
+                "Translation granule not supported in HW",
+            ));
+        }
+
+        let layout = generic_mmu::virt_mem_layout();
+        address_space_size_checked(layout.addr_space_size()).map_err(MmuEnableError::Other)?;
+
+        // Prepare the memory attribute indirection register.
+        self.set_up_mair();
+
+        // Populate translation tables and switch the "Translation Table Base Register" over.
+        KERNEL_TABLES.lock(|tables| -> Result<(), MmuEnableError> {
+            if TTBR1_EL1.get_baddr() == tables.phys_base_address() {
+                // unlikely
+                return Err(MmuEnableError::AlreadyEnabled);
+            }
+
+            tables
+                .populate_tt_entries(&layout)
+                .map_err(MmuEnableError::Other)?;
+
+            // Make the new tables visible to the table walker.
+            barrier::dsb(barrier::ISHST);
+
+            self.replace_ttbr1(tables.phys_base_address());
+
+            Ok(())
+        })?;
+
+        // From here on, the kernel runs on TTBR1 only.
+        self.configure_translation_control();
+        barrier::isb(barrier::SY);
+        self.tlb_invalidate_all();
+
+        // Make sure data and instruction caching are on.
+        SCTLR_EL1.modify(SCTLR_EL1::C::Cacheable + SCTLR_EL1::I::Cacheable);
+        barrier::isb(barrier::SY);
+
+        Ok(())
+    }
+
+    unsafe fn enable_mmu_and_caching_secondary(&self) -> Result<(), MmuEnableError> {
+        if !self.is_enabled() {
+            // unlikely
+            return Err(MmuEnableError::Other("MMU not enabled by the boot code"));
+        }
+
+        let phys_base_address = KERNEL_TABLES.lock(|tables| tables.phys_base_address());
+        if TTBR1_EL1.get_baddr() == phys_base_address {
+            // unlikely
+            return Err(MmuEnableError::AlreadyEnabled);
+        }
+
+        // The boot core populated the tables and made them visible already.
+        self.set_up_mair();
+        self.replace_ttbr1(phys_base_address);
+
+        self.configure_translation_control();
+        barrier::isb(barrier::SY);
+        self.tlb_invalidate_all();
+
+        SCTLR_EL1.modify(SCTLR_EL1::C::Cacheable + SCTLR_EL1::I::Cacheable);
+        barrier::isb(barrier::SY);
+
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn is_enabled(&self) -> bool {
+        SCTLR_EL1.matches_all(SCTLR_EL1::M::Enable)
+    }
+
+    unsafe fn map_pages(
+        &self,
+        virt_addr: usize,
+        phys_addr: usize,
+        num_pages: usize,
+        attribute_fields: &AttributeFields,
+    ) -> Result<(), &'static str> {
+        KERNEL_TABLES
+            .lock(|tables| tables.map_pages(virt_addr, phys_addr, num_pages, attribute_fields))
+    }
+
+    unsafe fn unmap_pages(&self, virt_addr: usize, num_pages: usize) -> Result<(), &'static str> {
+        KERNEL_TABLES.lock(|tables| tables.unmap_pages(virt_addr, num_pages))
+    }
+
+    unsafe fn protect_pages(
+        &self,
+        virt_addr: usize,
+        num_pages: usize,
+        attribute_fields: &AttributeFields,
+    ) -> Result<(), &'static str> {
+        KERNEL_TABLES.lock(|tables| tables.protect_pages(virt_addr, num_pages, attribute_fields))
+    }
+
+    unsafe fn activate_user_table(&self, phys_table_base: u64, asid: u16) {
+        TTBR0_EL1
+            .write(TTBR0_EL1::ASID.val(asid as u64) + TTBR0_EL1::BADDR.val(phys_table_base >> 1));
+        TCR_EL1.modify(TCR_EL1::EPD0::EnableTTBR0Walks);
+        barrier::isb(barrier::SY);
+    }
+
+    unsafe fn deactivate_user_table(&self) {
+        // ASID 0 is never handed out, so TLB entries of the old table don't match anymore.
+        TCR_EL1.modify(TCR_EL1::EPD0::DisableTTBR0Walks);
+        TTBR0_EL1.set(0);
+        barrier::isb(barrier::SY);
+    }
+
+    fn invalidate_asid(&self, asid: u16) {
+        // The operand holds the ASID in bits [63:48].
+        let operand = (asid as u64) << 48;
+
+        barrier::dsb(barrier::ISHST);
+        unsafe {
+            asm!(
+                "tlbi aside1is, {operand}",
+                operand = in(reg) operand,
+                options(nostack, preserves_flags)
+            );
+        }
+        barrier::dsb(barrier::ISH);
+        barrier::isb(barrier::SY);
+    }
+}
+
+/// Returns true if the hardware supports the kernel's translation granule.
+#[cfg(not(feature = "granule_4k"))]
+fn granule_supported() -> bool {
+    ID_AA64MMFR0_EL1.matches_all(ID_AA64MMFR0_EL1::TGran64::Supported)
+}
+
+/// Returns true if the hardware supports the kernel's translation granule.
+#[cfg(feature = "granule_4k")]
+fn granule_supported() -> bool {
+    ID_AA64MMFR0_EL1.matches_all(ID_AA64MMFR0_EL1::TGran4::Supported)
+}
+
+/// Constants for indexing the MAIR_EL1.
+#[allow(dead_code)]
+pub mod mair {
+    pub const DEVICE: u64 = 0;
+    pub const NORMAL: u64 = 1;
+}
+
+static MMU: MemoryManagementUnit = MemoryManagementUnit;
+
+/// Checks a board-supplied address space size for architectural restrictions.
+fn address_space_size_checked(size: usize) -> Result<(), &'static str> {
+    if !size.is_power_of_two() {
+        return Err("Address space size is not a power of two");
+    }
+
+    // Check for 48 bit virtual address size as maximum, which is supported by any ARMv8
+    // version.
+    if size > (1 << 48) {
+        return Err("Address space size exceeds 48 bits");
+    }
+
+    if size > translation_table::MAX_ADDR_SPACE_SIZE {
+        return Err("Address space size exceeds the kernel translation tables");
+    }
+
+    // TCR_EL1 is set up for the whole TTBR1 range.
+    if size != TTBR1_RANGE_SIZE {
+        return Err("Address space size does not match the TTBR1 range");
+    }
+
+    Ok(())
+}
+
+/// Return a reference to the MMU instance.
+pub fn mmu() -> &'static impl generic_mmu::interface::Mmu {
+    &MMU
+}
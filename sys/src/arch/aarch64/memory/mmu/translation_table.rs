@@ -0,0 +1,859 @@
+use core::{arch::asm, convert};
+
+use aarch64_cpu::{
+    asm::barrier,
+    registers::{Readable, Writeable},
+};
+use tock_registers::{register_bitfields, registers::InMemoryRegister};
+
+use crate::memory::{
+    frame_alloc::frame_allocator,
+    mmu::{AccessPermissions, AttributeFields, KernelVirtualLayout, MemAttributes},
+    phys_to_virt, virt_to_phys, KERNEL_VIRT_OFFSET,
+};
+
+use crate::memory::mmu::arch_mmu::{self, KernelGranule};
+
+// A table descriptor.
+register_bitfields! {
+    u64,
+    STAGE1_TABLE_DESCRIPTOR [
+        /// Physical address of the next level table, see `ADDR_FIELD_SHIFT`.
+        NEXT_LEVEL_TABLE_ADDR OFFSET(12) NUMBITS(36) [],
+
+        TYPE OFFSET(1) NUMBITS(1) [
+            Block = 0,
+            Table = 1,
+        ],
+
+        VALID OFFSET(0) NUMBITS(1) [
+            False = 0,
+            True = 1,
+        ],
+    ],
+}
+
+// A block (lvl1, lvl2) or page (lvl3) descriptor.
+register_bitfields! {
+    u64,
+    STAGE1_PAGE_DESCRIPTOR [
+        /// Unprivileged execute-never.
+        UXN OFFSET(54) NUMBITS(1) [
+            False = 0,
+            True = 1,
+        ],
+
+        /// Privileged execute-never.
+        PXN OFFSET(53) NUMBITS(1) [
+            False = 0,
+            True = 1,
+        ],
+
+        /// Physical address of the block or page, see `ADDR_FIELD_SHIFT`.
+        OUTPUT_ADDR OFFSET(12) NUMBITS(36) [],
+
+        /// Not global. The TLB tags the translation with the ASID of the current TTBR0.
+        NG OFFSET(11) NUMBITS(1) [
+            False = 0,
+            True = 1,
+        ],
+
+        /// Access flag.
+        AF OFFSET(10) NUMBITS(1) [
+            False = 0,
+            True = 1,
+        ],
+
+        /// Shareability field.
+        SH OFFSET(8) NUMBITS(2) [
+            OuterShareable = 0b10,
+            InnerShareable = 0b11,
+        ],
+
+        /// Access Permissions.
+        AP OFFSET(6) NUMBITS(2) [
+            RW_EL1 = 0b00,
+            RW_EL1_EL0 = 0b01,
+            RO_EL1 = 0b10,
+            RO_EL1_EL0 = 0b11,
+        ],
+
+        /// Memory attributes index into the MAIR_EL1 register.
+        AttrIndx OFFSET(2) NUMBITS(3) [],
+
+        /// `Block` at lvl1 and lvl2, `Page` at lvl3.
+        TYPE OFFSET(1) NUMBITS(1) [
+            Block = 0,
+            Page = 1,
+        ],
+
+        VALID OFFSET(0) NUMBITS(1) [
+            False = 0,
+            True = 1,
+        ],
+    ],
+}
+
+/// Descriptors hold addresses in 4 KiB units, whatever the granule. With larger granules, the
+/// low bits of the field stay zero.
+const ADDR_FIELD_SHIFT: usize = 12;
+
+/// The largest address space the kernel translation tables can describe.
+pub const MAX_ADDR_SPACE_SIZE: usize = 4 * 1024 * 1024 * 1024;
+
+/// A table is one granule in size and holds 8 byte descriptors, so every level resolves this many
+/// bits of the virtual address.
+const BITS_PER_LEVEL: usize = KernelGranule::SHIFT - 3;
+
+const ENTRIES_PER_TABLE: usize = 1 << BITS_PER_LEVEL;
+
+/// Pages are mapped at this level.
+const LAST_LEVEL: usize = 3;
+
+/// The walk starts at the highest level needed to cover `MAX_ADDR_SPACE_SIZE`. With the 64 KiB
+/// granule and 4 GiB, that's lvl2. With the 4 KiB granule, it's lvl1 for a 3-level walk, or lvl0
+/// for a 4-level walk once the address space exceeds 512 GiB.
+const START_LEVEL: usize = LAST_LEVEL
+    - (MAX_ADDR_SPACE_SIZE.trailing_zeros() as usize - KernelGranule::SHIFT - 1) / BITS_PER_LEVEL;
+
+/// Entries used in the start level table.
+const NUM_ROOT_ENTRIES: usize = MAX_ADDR_SPACE_SIZE >> level_shift(START_LEVEL);
+
+/// log2 of the size an entry at `level` maps.
+const fn level_shift(level: usize) -> usize {
+    KernelGranule::SHIFT + BITS_PER_LEVEL * (LAST_LEVEL - level)
+}
+
+/// Granule specific table parameters.
+#[cfg(not(feature = "granule_4k"))]
+mod granule {
+    use super::{Descriptor, ENTRIES_PER_TABLE};
+
+    /// Lowest level holding block descriptors. lvl1 blocks would need 52 bit output addresses.
+    pub const MIN_BLOCK_LEVEL: usize = 2;
+
+    /// The start level table plus a lvl3 table for each of the two 512 MiB the DRAM spans and one
+    /// for the MMIO, with room to spare.
+    pub const NUM_TABLES: usize = 8;
+
+    /// A translation table of any level.
+    #[derive(Copy, Clone)]
+    #[repr(C)]
+    #[repr(align(65536))]
+    pub struct Table(pub [Descriptor; ENTRIES_PER_TABLE]);
+}
+
+/// Granule specific table parameters.
+#[cfg(feature = "granule_4k")]
+mod granule {
+    use super::{Descriptor, ENTRIES_PER_TABLE};
+
+    /// Lowest level holding block descriptors.
+    pub const MIN_BLOCK_LEVEL: usize = 1;
+
+    /// The start level table, the lvl2 tables and a lvl3 table for every 2 MiB the layout
+    /// describes. The DRAM alone takes 474 of them, the MMIO remap window up to 13 more.
+    pub const NUM_TABLES: usize = 512;
+
+    /// A translation table of any level.
+    #[derive(Copy, Clone)]
+    #[repr(C)]
+    #[repr(align(4096))]
+    pub struct Table(pub [Descriptor; ENTRIES_PER_TABLE]);
+}
+
+use granule::{Table, MIN_BLOCK_LEVEL, NUM_TABLES};
+
+/// A descriptor of any level. It is either invalid or points to a table, a block or a page.
+#[derive(Copy, Clone)]
+#[repr(C)]
+struct Descriptor {
+    value: u64,
+}
+
+impl Descriptor {
+    /// Create an instance.
+    pub const fn new_zeroed() -> Self {
+        Self { value: 0 }
+    }
+
+    /// Create a table descriptor pointing to the supplied address.
+    pub fn from_next_lvl_table_addr(phys_next_lvl_table_addr: usize) -> Self {
+        let val = InMemoryRegister::<u64, STAGE1_TABLE_DESCRIPTOR::Register>::new(0);
+
+        let shifted = phys_next_lvl_table_addr >> ADDR_FIELD_SHIFT;
+        val.write(
+            STAGE1_TABLE_DESCRIPTOR::NEXT_LEVEL_TABLE_ADDR.val(shifted as u64)
+                + STAGE1_TABLE_DESCRIPTOR::TYPE::Table
+                + STAGE1_TABLE_DESCRIPTOR::VALID::True,
+        );
+
+        Self { value: val.get() }
+    }
+
+    /// Create a block descriptor, or a page descriptor at lvl3.
+    pub fn from_output_addr(
+        level: usize,
+        phys_output_addr: usize,
+        attribute_fields: &AttributeFields,
+    ) -> Self {
+        let val = InMemoryRegister::<u64, STAGE1_PAGE_DESCRIPTOR::Register>::new(0);
+
+        let shifted = phys_output_addr as u64 >> ADDR_FIELD_SHIFT;
+        val.write(
+            STAGE1_PAGE_DESCRIPTOR::OUTPUT_ADDR.val(shifted)
+                + STAGE1_PAGE_DESCRIPTOR::AF::True
+                + Self::block_or_page(level)
+                + STAGE1_PAGE_DESCRIPTOR::VALID::True
+                + (*attribute_fields).into(),
+        );
+
+        Self { value: val.get() }
+    }
+
+    fn block_or_page(
+        level: usize,
+    ) -> tock_registers::fields::FieldValue<u64, STAGE1_PAGE_DESCRIPTOR::Register> {
+        if level == LAST_LEVEL {
+            STAGE1_PAGE_DESCRIPTOR::TYPE::Page
+        } else {
+            STAGE1_PAGE_DESCRIPTOR::TYPE::Block
+        }
+    }
+
+    /// Returns the valid bit.
+    fn is_valid(&self) -> bool {
+        InMemoryRegister::<u64, STAGE1_PAGE_DESCRIPTOR::Register>::new(self.value)
+            .is_set(STAGE1_PAGE_DESCRIPTOR::VALID)
+    }
+
+    /// Returns true for a valid table descriptor at `level`.
+    fn is_table(&self, level: usize) -> bool {
+        level != LAST_LEVEL
+            && InMemoryRegister::<u64, STAGE1_TABLE_DESCRIPTOR::Register>::new(self.value)
+                .matches_all(
+                    STAGE1_TABLE_DESCRIPTOR::TYPE::Table + STAGE1_TABLE_DESCRIPTOR::VALID::True,
+                )
+    }
+
+    /// Returns the address of the next level table.
+    fn next_lvl_table_addr(&self) -> usize {
+        let shifted = InMemoryRegister::<u64, STAGE1_TABLE_DESCRIPTOR::Register>::new(self.value)
+            .read(STAGE1_TABLE_DESCRIPTOR::NEXT_LEVEL_TABLE_ADDR);
+
+        (shifted as usize) << ADDR_FIELD_SHIFT
+    }
+
+    /// Returns the output address of a block or page.
+    fn output_addr(&self) -> usize {
+        let shifted = InMemoryRegister::<u64, STAGE1_PAGE_DESCRIPTOR::Register>::new(self.value)
+            .read(STAGE1_PAGE_DESCRIPTOR::OUTPUT_ADDR);
+
+        (shifted as usize) << ADDR_FIELD_SHIFT
+    }
+}
+
+/// Invalidate the TLB entries of a page in the inner shareable domain.
+#[inline(always)]
+fn tlb_invalidate_page(virt_addr: usize) {
+    // The operand holds VA[55:12] in its low 44 bits.
+    let va = ((virt_addr >> 12) & ((1 << 44) - 1)) as u64;
+
+    unsafe {
+        asm!(
+            "tlbi vaae1is, {va}",
+            va = in(reg) va,
+            options(nostack, preserves_flags)
+        );
+    }
+}
+
+/// Replace a live descriptor, following the break-before-make sequence if the old descriptor
+/// was valid. `virt_addr` is the start of the range it maps. The caller must issue an ISB
+/// before relying on the new mapping.
+fn set_descriptor(desc: &mut Descriptor, virt_addr: usize, new: Descriptor) {
+    // Make a next level table the new descriptor points to visible to the table walker.
+    barrier::dsb(barrier::ISHST);
+
+    if desc.is_valid() {
+        // Break: invalidate the entry and make sure no TLB still holds it.
+        unsafe { core::ptr::write_volatile(&mut desc.value, 0) };
+        barrier::dsb(barrier::ISHST);
+        tlb_invalidate_page(virt_addr);
+        barrier::dsb(barrier::ISH);
+    }
+
+    // Make.
+    unsafe { core::ptr::write_volatile(&mut desc.value, new.value) };
+    barrier::dsb(barrier::ISHST);
+}
+
+/// Convert the kernel's generic memory attributes to HW-specific attributes of the MMU.
+impl convert::From<AttributeFields>
+    for tock_registers::fields::FieldValue<u64, STAGE1_PAGE_DESCRIPTOR::Register>
+{
+    fn from(value: AttributeFields) -> Self {
+        // Memory attributes.
+        let mut desc = match value.mem_attributes {
+            MemAttributes::CacheableDram => {
+                STAGE1_PAGE_DESCRIPTOR::SH::InnerShareable
+                    + STAGE1_PAGE_DESCRIPTOR::AttrIndx.val(arch_mmu::mair::NORMAL)
+            }
+            MemAttributes::Device => {
+                STAGE1_PAGE_DESCRIPTOR::SH::OuterShareable
+                    + STAGE1_PAGE_DESCRIPTOR::AttrIndx.val(arch_mmu::mair::DEVICE)
+            }
+        };
+
+        // Access Permissions. Pages accessible from EL0 are tagged with the ASID.
+        desc += match value.acc_perms {
+            AccessPermissions::ReadOnly => STAGE1_PAGE_DESCRIPTOR::AP::RO_EL1,
+            AccessPermissions::ReadWrite => STAGE1_PAGE_DESCRIPTOR::AP::RW_EL1,
+            AccessPermissions::UserReadOnly => {
+                STAGE1_PAGE_DESCRIPTOR::AP::RO_EL1_EL0 + STAGE1_PAGE_DESCRIPTOR::NG::True
+            }
+            AccessPermissions::UserReadWrite => {
+                STAGE1_PAGE_DESCRIPTOR::AP::RW_EL1_EL0 + STAGE1_PAGE_DESCRIPTOR::NG::True
+            }
+        };
+
+        // The execute-never attribute is mapped to PXN for kernel pages and to UXN for user
+        // pages. The kernel never executes user pages and user code never executes kernel pages.
+        desc += match (value.execute_never, value.acc_perms.is_user()) {
+            (true, _) => STAGE1_PAGE_DESCRIPTOR::PXN::True + STAGE1_PAGE_DESCRIPTOR::UXN::True,
+            (false, false) => {
+                STAGE1_PAGE_DESCRIPTOR::PXN::False + STAGE1_PAGE_DESCRIPTOR::UXN::True
+            }
+            (false, true) => STAGE1_PAGE_DESCRIPTOR::PXN::True + STAGE1_PAGE_DESCRIPTOR::UXN::False,
+        };
+
+        desc
+    }
+}
+
+trait StartAddr {
+    fn phys_start_addr_u64(&self) -> u64;
+    fn phys_start_addr_usize(&self) -> usize;
+}
+
+impl<T, const N: usize> StartAddr for [T; N] {
+    fn phys_start_addr_u64(&self) -> u64 {
+        self.phys_start_addr_usize() as u64
+    }
+
+    fn phys_start_addr_usize(&self) -> usize {
+        virt_to_phys(self as *const _ as usize)
+    }
+}
+
+/// The translation table the boot code enables the MMU with. It maps the first 4 GiB of physical
+/// memory with blocks in a single start level table, the last of which holds the MMIO and is
+/// mapped as device memory. Installed in both TTBR0 and TTBR1, it covers the boot code running
+/// from physical addresses as well as the kernel running from its linked addresses.
+#[repr(C)]
+#[repr(align(4096))]
+pub struct BootTranslationTable {
+    entries: [u64; NUM_ROOT_ENTRIES],
+}
+
+impl BootTranslationTable {
+    /// Create an instance.
+    pub const fn new() -> Self {
+        // A 4-level walk starts at lvl0, which can't hold blocks.
+        const { assert!(START_LEVEL >= MIN_BLOCK_LEVEL) };
+
+        let normal = STAGE1_PAGE_DESCRIPTOR::SH::InnerShareable.value
+            | STAGE1_PAGE_DESCRIPTOR::AttrIndx
+                .val(arch_mmu::mair::NORMAL)
+                .value;
+        let device = STAGE1_PAGE_DESCRIPTOR::SH::OuterShareable.value
+            | STAGE1_PAGE_DESCRIPTOR::AttrIndx
+                .val(arch_mmu::mair::DEVICE)
+                .value
+            | STAGE1_PAGE_DESCRIPTOR::PXN::True.value;
+
+        let mut entries = [0; NUM_ROOT_ENTRIES];
+        let mut i = 0;
+        while i < NUM_ROOT_ENTRIES {
+            let attributes = if i == NUM_ROOT_ENTRIES - 1 {
+                device
+            } else {
+                normal
+            };
+
+            entries[i] = STAGE1_PAGE_DESCRIPTOR::OUTPUT_ADDR
+                .val(((i << level_shift(START_LEVEL)) >> ADDR_FIELD_SHIFT) as u64)
+                .value
+                | STAGE1_PAGE_DESCRIPTOR::UXN::True.value
+                | STAGE1_PAGE_DESCRIPTOR::AF::True.value
+                | STAGE1_PAGE_DESCRIPTOR::AP::RW_EL1.value
+                | STAGE1_PAGE_DESCRIPTOR::TYPE::Block.value
+                | STAGE1_PAGE_DESCRIPTOR::VALID::True.value
+                | attributes;
+
+            i += 1;
+        }
+
+        Self { entries }
+    }
+}
+
+/// The kernel translation tables. Next level tables come from a fixed pool. Ranges the layout
+/// does not describe are mapped with blocks, unmapped ranges get no tables until pages are mapped
+/// there, everything else is mapped down to pages.
+#[repr(C)]
+pub struct KernelTranslationTable {
+    /// `tables[0]` is the start level table, the others are handed out on demand.
+    tables: [Table; NUM_TABLES],
+
+    /// Number of next level tables handed out. Zero, so the whole struct stays in .bss.
+    num_next_lvl_tables: usize,
+}
+
+impl KernelTranslationTable {
+    /// Create an instance.
+    pub const fn new() -> Self {
+        Self {
+            tables: [Table([Descriptor::new_zeroed(); ENTRIES_PER_TABLE]); NUM_TABLES],
+            num_next_lvl_tables: 0,
+        }
+    }
+
+    /// Take an unused table from the pool. Tables are never given back, so it is still zeroed.
+    fn alloc_table(&mut self) -> Result<usize, &'static str> {
+        if self.num_next_lvl_tables >= NUM_TABLES - 1 {
+            return Err("Out of translation tables");
+        }
+
+        self.num_next_lvl_tables += 1;
+
+        Ok(self.num_next_lvl_tables)
+    }
+
+    /// Return the pool index of the table at a physical address.
+    fn table_nr(&self, phys_table_addr: usize) -> Result<usize, &'static str> {
+        let nr = phys_table_addr.wrapping_sub(self.tables.phys_start_addr_usize())
+            >> KernelGranule::SHIFT;
+
+        if nr == 0 || nr > self.num_next_lvl_tables {
+            return Err("Table descriptor outside the table pool");
+        }
+
+        Ok(nr)
+    }
+
+    /// Return the physical address of a table of the pool.
+    fn table_phys_addr(&self, nr: usize) -> usize {
+        self.tables[nr].0.phys_start_addr_usize()
+    }
+
+    /// Iterates over the translation table entries covering the layout's address space and fills
+    /// them at once. Entries beyond it stay invalid.
+    /// # Safety
+    pub unsafe fn populate_tt_entries(
+        &mut self,
+        layout: &KernelVirtualLayout,
+    ) -> Result<(), &'static str> {
+        let num_entries = layout.addr_space_size() >> level_shift(START_LEVEL);
+
+        self.populate_table(0, START_LEVEL, KERNEL_VIRT_OFFSET, num_entries, layout)
+    }
+
+    /// Fill the first `num_entries` entries of table `nr` at `level`, which maps `virt_addr`
+    /// onwards.
+    fn populate_table(
+        &mut self,
+        nr: usize,
+        level: usize,
+        virt_addr: usize,
+        num_entries: usize,
+        layout: &KernelVirtualLayout,
+    ) -> Result<(), &'static str> {
+        for i in 0..num_entries {
+            let entry_addr = virt_addr + (i << level_shift(level));
+            let entry_range = entry_addr..=entry_addr + ((1 << level_shift(level)) - 1);
+
+            let use_output_addr = level == LAST_LEVEL
+                || (level >= MIN_BLOCK_LEVEL && layout.is_default_mapped(entry_range.clone()));
+
+            let desc = if use_output_addr {
+                match layout.virt_addr_properties(entry_addr)? {
+                    Some((phys_output_addr, attribute_fields)) => {
+                        Descriptor::from_output_addr(level, phys_output_addr, &attribute_fields)
+                    }
+                    None => Descriptor::new_zeroed(),
+                }
+            } else if layout.is_unmapped(entry_range) {
+                // Tables get allocated once something is mapped here.
+                Descriptor::new_zeroed()
+            } else {
+                let next = self.alloc_table()?;
+                self.populate_table(next, level + 1, entry_addr, ENTRIES_PER_TABLE, layout)?;
+
+                Descriptor::from_next_lvl_table_addr(self.table_phys_addr(next))
+            };
+
+            self.tables[nr].0[i] = desc;
+        }
+
+        Ok(())
+    }
+
+    /// Return the physical address a page aligned virtual address is mapped to, if any.
+    fn page_output_addr(&self, virt_addr: usize) -> Result<Option<usize>, &'static str> {
+        let offset = virt_addr - KERNEL_VIRT_OFFSET;
+        let mut nr = 0;
+
+        for level in START_LEVEL..=LAST_LEVEL {
+            let desc = self.tables[nr].0[(offset >> level_shift(level)) & (ENTRIES_PER_TABLE - 1)];
+
+            if !desc.is_valid() {
+                return Ok(None);
+            }
+
+            if !desc.is_table(level) {
+                let offset_in_block = offset & ((1 << level_shift(level)) - 1);
+
+                return Ok(Some(desc.output_addr() + offset_in_block));
+            }
+
+            nr = self.table_nr(desc.next_lvl_table_addr())?;
+        }
+
+        unreachable!()
+    }
+
+    /// Return the lvl3 descriptor of a page aligned virtual address. Missing tables are allocated,
+    /// blocks on the way are an error.
+    fn page_descriptor_mut(&mut self, virt_addr: usize) -> Result<&mut Descriptor, &'static str> {
+        let offset = virt_addr - KERNEL_VIRT_OFFSET;
+        let mut nr = 0;
+
+        for level in START_LEVEL..LAST_LEVEL {
+            let index = (offset >> level_shift(level)) & (ENTRIES_PER_TABLE - 1);
+            let desc = self.tables[nr].0[index];
+
+            nr = if desc.is_table(level) {
+                self.table_nr(desc.next_lvl_table_addr())?
+            } else {
+                let entry_addr = virt_addr & !((1 << level_shift(level)) - 1);
+
+                self.add_next_lvl_table(nr, index, entry_addr)?
+            };
+        }
+
+        let index = (offset >> level_shift(LAST_LEVEL)) & (ENTRIES_PER_TABLE - 1);
+
+        Ok(&mut self.tables[nr].0[index])
+    }
+
+    /// Replace an invalid entry of table `nr` by an empty next level table, and return the table.
+    ///
+    /// Valid blocks are not split, as the break-before-make sequence would unmap the whole block
+    /// while other cores or this one may still use it. Memory pages get remapped in, like the DRAM
+    /// handed out by the frame allocator, must be described by the layout to be mapped down to
+    /// pages from the start.
+    fn add_next_lvl_table(
+        &mut self,
+        nr: usize,
+        index: usize,
+        entry_addr: usize,
+    ) -> Result<usize, &'static str> {
+        if self.tables[nr].0[index].is_valid() {
+            return Err("Refusing to split a block mapping");
+        }
+
+        let next = self.alloc_table()?;
+
+        let new = Descriptor::from_next_lvl_table_addr(self.table_phys_addr(next));
+        set_descriptor(&mut self.tables[nr].0[index], entry_addr, new);
+
+        Ok(next)
+    }
+
+    /// Check that a page range lies completely within the table.
+    fn check_page_range(&self, virt_addr: usize, num_pages: usize) -> Result<(), &'static str> {
+        if num_pages == 0 {
+            return Err("Zero pages requested");
+        }
+
+        if !virt_addr.is_multiple_of(KernelGranule::SIZE) {
+            return Err("Virtual address is not page aligned");
+        }
+
+        let last_page = num_pages
+            .checked_sub(1)
+            .and_then(|x| x.checked_mul(KernelGranule::SIZE))
+            .and_then(|x| x.checked_add(virt_addr))
+            .ok_or("Virtual address range overflows")?;
+
+        if virt_addr < KERNEL_VIRT_OFFSET || (last_page - KERNEL_VIRT_OFFSET) >= MAX_ADDR_SPACE_SIZE
+        {
+            return Err("Virtual address out of range");
+        }
+
+        Ok(())
+    }
+
+    /// Replace the lvl3 descriptor of a page.
+    fn set_page_descriptor(
+        &mut self,
+        virt_addr: usize,
+        new: Descriptor,
+    ) -> Result<(), &'static str> {
+        let desc = self.page_descriptor_mut(virt_addr)?;
+        set_descriptor(desc, virt_addr, new);
+
+        Ok(())
+    }
+
+    /// Map `num_pages` pages starting at `virt_addr` to the physical pages starting at
+    /// `phys_addr`.
+    pub fn map_pages(
+        &mut self,
+        virt_addr: usize,
+        phys_addr: usize,
+        num_pages: usize,
+        attribute_fields: &AttributeFields,
+    ) -> Result<(), &'static str> {
+        self.check_page_range(virt_addr, num_pages)?;
+
+        if !phys_addr.is_multiple_of(KernelGranule::SIZE) {
+            return Err("Physical address is not page aligned");
+        }
+
+        for i in 0..num_pages {
+            let offset = i << KernelGranule::SHIFT;
+            let new =
+                Descriptor::from_output_addr(LAST_LEVEL, phys_addr + offset, attribute_fields);
+
+            self.set_page_descriptor(virt_addr + offset, new)?;
+        }
+        barrier::isb(barrier::SY);
+
+        Ok(())
+    }
+
+    /// Remove the mappings of `num_pages` pages starting at `virt_addr`.
+    pub fn unmap_pages(&mut self, virt_addr: usize, num_pages: usize) -> Result<(), &'static str> {
+        self.check_page_range(virt_addr, num_pages)?;
+
+        for i in 0..num_pages {
+            let page_addr = virt_addr + (i << KernelGranule::SHIFT);
+
+            // Don't allocate tables just to keep an unmapped page unmapped.
+            if self.page_output_addr(page_addr)?.is_some() {
+                self.set_page_descriptor(page_addr, Descriptor::new_zeroed())?;
+            }
+        }
+        barrier::isb(barrier::SY);
+
+        Ok(())
+    }
+
+    /// Change the attributes of `num_pages` mapped pages starting at `virt_addr`, keeping their
+    /// output addresses.
+    pub fn protect_pages(
+        &mut self,
+        virt_addr: usize,
+        num_pages: usize,
+        attribute_fields: &AttributeFields,
+    ) -> Result<(), &'static str> {
+        self.check_page_range(virt_addr, num_pages)?;
+
+        // Refuse up front instead of leaving the range half changed.
+        for i in 0..num_pages {
+            if self
+                .page_output_addr(virt_addr + (i << KernelGranule::SHIFT))?
+                .is_none()
+            {
+                return Err("Page is not mapped");
+            }
+        }
+
+        for i in 0..num_pages {
+            let page_addr = virt_addr + (i << KernelGranule::SHIFT);
+            let output_addr = self
+                .page_output_addr(page_addr)?
+                .ok_or("Page is not mapped")?;
+
+            let new = Descriptor::from_output_addr(LAST_LEVEL, output_addr, attribute_fields);
+            self.set_page_descriptor(page_addr, new)?;
+        }
+        barrier::isb(barrier::SY);
+
+        Ok(())
+    }
+
+    /// The translation table's base address to be used for programming the MMU.
+    pub fn phys_base_address(&self) -> u64 {
+        self.tables[0].0.phys_start_addr_u64()
+    }
+}
+
+/// The translation tables of a user address space, installed in TTBR0. It covers the same
+/// `MAX_ADDR_SPACE_SIZE` as the kernel tables, starting at address zero. Unlike the kernel tables,
+/// every table is a frame of its own, taken from the frame allocator on demand and given back on
+/// drop. User tables only ever hold pages.
+pub struct UserTranslationTable {
+    /// Physical address of the start level table.
+    phys_root: usize,
+}
+
+impl UserTranslationTable {
+    /// Create an instance with nothing mapped.
+    pub fn new() -> Result<Self, &'static str> {
+        Ok(Self {
+            phys_root: Self::alloc_table()?,
+        })
+    }
+
+    /// Take a zeroed frame from the frame allocator. A table is exactly one frame.
+    fn alloc_table() -> Result<usize, &'static str> {
+        let phys_table_addr = frame_allocator().alloc_frame()?;
+
+        unsafe { core::ptr::write_bytes(phys_to_virt(phys_table_addr) as *mut Table, 0, 1) };
+
+        Ok(phys_table_addr)
+    }
+
+    /// Return the table at a physical address, through the kernel's linear mapping.
+    fn table_mut(&mut self, phys_table_addr: usize) -> &mut Table {
+        unsafe { &mut *(phys_to_virt(phys_table_addr) as *mut Table) }
+    }
+
+    /// Return the table at a physical address, through the kernel's linear mapping.
+    fn table(&self, phys_table_addr: usize) -> &Table {
+        unsafe { &*(phys_to_virt(phys_table_addr) as *const Table) }
+    }
+
+    /// Check that a page range lies completely within the table.
+    fn check_page_range(&self, virt_addr: usize, num_pages: usize) -> Result<(), &'static str> {
+        if num_pages == 0 {
+            return Err("Zero pages requested");
+        }
+
+        if !virt_addr.is_multiple_of(KernelGranule::SIZE) {
+            return Err("Virtual address is not page aligned");
+        }
+
+        let end_exclusive = num_pages
+            .checked_mul(KernelGranule::SIZE)
+            .and_then(|x| x.checked_add(virt_addr))
+            .ok_or("Virtual address range overflows")?;
+
+        if end_exclusive > MAX_ADDR_SPACE_SIZE {
+            return Err("Virtual address out of range");
+        }
+
+        Ok(())
+    }
+
+    /// Return the lvl3 descriptor of a page aligned virtual address. Missing tables are allocated.
+    fn page_descriptor_mut(&mut self, virt_addr: usize) -> Result<&mut Descriptor, &'static str> {
+        let mut phys_table_addr = self.phys_root;
+
+        for level in START_LEVEL..LAST_LEVEL {
+            let index = (virt_addr >> level_shift(level)) & (ENTRIES_PER_TABLE - 1);
+            let desc = &mut self.table_mut(phys_table_addr).0[index];
+
+            phys_table_addr = if desc.is_table(level) {
+                desc.next_lvl_table_addr()
+            } else {
+                let next = Self::alloc_table()?;
+                let entry_addr = virt_addr & !((1 << level_shift(level)) - 1);
+                set_descriptor(desc, entry_addr, Descriptor::from_next_lvl_table_addr(next));
+
+                next
+            };
+        }
+
+        let index = (virt_addr >> level_shift(LAST_LEVEL)) & (ENTRIES_PER_TABLE - 1);
+
+        Ok(&mut self.table_mut(phys_table_addr).0[index])
+    }
+
+    /// Return the physical address a virtual address is mapped to, if any.
+    pub fn translate(&self, virt_addr: usize) -> Option<usize> {
+        if virt_addr >= MAX_ADDR_SPACE_SIZE {
+            return None;
+        }
+
+        let mut phys_table_addr = self.phys_root;
+
+        for level in START_LEVEL..=LAST_LEVEL {
+            let index = (virt_addr >> level_shift(level)) & (ENTRIES_PER_TABLE - 1);
+            let desc = self.table(phys_table_addr).0[index];
+
+            if !desc.is_valid() {
+                return None;
+            }
+
+            if !desc.is_table(level) {
+                let offset_in_page = virt_addr & ((1 << level_shift(level)) - 1);
+
+                return Some(desc.output_addr() + offset_in_page);
+            }
+
+            phys_table_addr = desc.next_lvl_table_addr();
+        }
+
+        unreachable!()
+    }
+
+    /// Map `num_pages` pages starting at `virt_addr` to the physical pages starting at
+    /// `phys_addr`, replacing any existing mapping.
+    pub fn map_pages(
+        &mut self,
+        virt_addr: usize,
+        phys_addr: usize,
+        num_pages: usize,
+        attribute_fields: &AttributeFields,
+    ) -> Result<(), &'static str> {
+        self.check_page_range(virt_addr, num_pages)?;
+
+        if !phys_addr.is_multiple_of(KernelGranule::SIZE) {
+            return Err("Physical address is not page aligned");
+        }
+
+        for i in 0..num_pages {
+            let offset = i << KernelGranule::SHIFT;
+            let new =
+                Descriptor::from_output_addr(LAST_LEVEL, phys_addr + offset, attribute_fields);
+
+            let desc = self.page_descriptor_mut(virt_addr + offset)?;
+            set_descriptor(desc, virt_addr + offset, new);
+        }
+        barrier::isb(barrier::SY);
+
+        Ok(())
+    }
+
+    /// Give a table and all tables below it back to the frame allocator.
+    fn free_table(&self, phys_table_addr: usize, level: usize) {
+        if level != LAST_LEVEL {
+            for index in 0..ENTRIES_PER_TABLE {
+                let desc = self.table(phys_table_addr).0[index];
+
+                if desc.is_table(level) {
+                    self.free_table(desc.next_lvl_table_addr(), level + 1);
+                }
+            }
+        }
+
+        if let Err(x) = frame_allocator().free_frames(phys_table_addr, 1) {
+            panic!("Freeing user translation table: {}", x);
+        }
+    }
+
+    /// The translation table's base address to be used for programming the MMU.
+    pub fn phys_base_address(&self) -> u64 {
+        self.phys_root as u64
+    }
+}
+
+impl Drop for UserTranslationTable {
+    fn drop(&mut self) {
+        self.free_table(self.phys_root, START_LEVEL);
+    }
+}
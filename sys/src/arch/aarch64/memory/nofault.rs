@@ -0,0 +1,45 @@
+//! Architectural memory accesses that may fault.
+
+use core::{arch::global_asm, cell::UnsafeCell};
+
+use crate::exception::fixup::{fixup_manager, FixupDescriptor};
+
+// Assembly counterpart to this file.
+global_asm!(include_str!("nofault.S"));
+
+extern "C" {
+    fn __copy_nofault(dst: *mut u8, src: *const u8, len: usize) -> usize;
+}
+
+extern "Rust" {
+    static __copy_nofault_load: UnsafeCell<()>;
+    static __copy_nofault_fixup: UnsafeCell<()>;
+}
+
+/// Copy `dst.len()` bytes starting at `src_addr` into `dst`. Returns an error instead of faulting
+/// if part of the source can not be read, in which case `dst` holds the bytes copied so far.
+pub fn copy_from_nofault(dst: &mut [u8], src_addr: usize) -> Result<(), &'static str> {
+    let not_copied = unsafe { __copy_nofault(dst.as_mut_ptr(), src_addr as *const u8, dst.len()) };
+
+    if not_copied != 0 {
+        return Err("Source address not readable");
+    }
+
+    Ok(())
+}
+
+/// Register the fixups of the functions in this file.
+pub fn register_fixups() -> Result<(), &'static str> {
+    let (load, fixup) = unsafe {
+        (
+            __copy_nofault_load.get() as usize,
+            __copy_nofault_fixup.get() as usize,
+        )
+    };
+
+    fixup_manager().register_fixup(FixupDescriptor::new(
+        "copy_from_nofault",
+        load..=load,
+        fixup,
+    ))
+}
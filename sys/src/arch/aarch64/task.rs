@@ -0,0 +1,111 @@
+//! Architectural user task entry and exit.
+
+use core::{
+    arch::global_asm,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use aarch64_cpu::registers::{SPSR_EL1, TTBR0_EL1};
+use tock_registers::interfaces::Readable;
+
+use crate::{
+    memory::mmu::{self, interface::Mmu},
+    task::TaskExit,
+};
+
+// Assembly counterpart to this file.
+global_asm!(
+    include_str!("task.S"),
+    CONST_SPSR_EL0 = const SPSR_EL1::M::EL0t.value,
+    USER_RETURN_SP = sym USER_RETURN_SP
+);
+
+/// The kernel stack pointer `__user_exit` returns to. Zero while no user task runs.
+static USER_RETURN_SP: AtomicUsize = AtomicUsize::new(0);
+
+/// The return value of `__user_enter`, handed over in x0 and x1.
+#[repr(C)]
+struct RawTaskExit {
+    /// Non-zero if the task was killed.
+    killed: u64,
+
+    /// The exit code of the task.
+    code: u64,
+}
+
+extern "C" {
+    fn __user_enter(entry: u64, user_sp: u64, arg: u64) -> RawTaskExit;
+    fn __user_exit(killed: u64, code: u64) -> !;
+}
+
+/// Run user code in EL0, with all interrupts unmasked, until the task exits or is killed.
+///
+/// # Safety
+///
+/// - A user address space mapping `entry` and the stack below `user_sp` must be active.
+pub unsafe fn enter_user(entry: usize, user_sp: usize, arg: u64) -> TaskExit {
+    let raw = __user_enter(entry as u64, user_sp as u64, arg);
+
+    match raw.killed {
+        0 => TaskExit::Exited(raw.code),
+        _ => TaskExit::Killed,
+    }
+}
+
+/// Leave the running user task and return from its `enter_user()`.
+pub fn exit_to_kernel(exit: TaskExit) -> ! {
+    assert!(
+        USER_RETURN_SP.load(Ordering::Relaxed) != 0,
+        "No user task running"
+    );
+
+    let (killed, code) = match exit {
+        TaskExit::Exited(code) => (0, code),
+        TaskExit::Killed => (1, 0),
+    };
+
+    unsafe { __user_exit(killed, code) }
+}
+
+/// The user task state that belongs to a kernel thread: where its `enter_user()` returns to, and
+/// the active user address space.
+#[derive(Copy, Clone)]
+pub struct UserContext {
+    return_sp: usize,
+    ttbr0: u64,
+}
+
+impl UserContext {
+    /// The state of a thread that never entered user mode.
+    pub const fn new() -> Self {
+        Self {
+            return_sp: 0,
+            ttbr0: 0,
+        }
+    }
+
+    /// Capture the state of the running thread.
+    pub fn save() -> Self {
+        Self {
+            return_sp: USER_RETURN_SP.load(Ordering::Relaxed),
+            ttbr0: TTBR0_EL1.get(),
+        }
+    }
+
+    /// Install the state of the thread about to run.
+    ///
+    /// # Safety
+    ///
+    /// - The address space of the saved state must still be alive.
+    pub unsafe fn restore(&self) {
+        USER_RETURN_SP.store(self.return_sp, Ordering::Relaxed);
+
+        match self.ttbr0 {
+            0 => mmu::mmu().deactivate_user_table(),
+            x => mmu::mmu().activate_user_table(
+                TTBR0_EL1::BADDR.read(x) << 1,
+                TTBR0_EL1::ASID.read(x) as u16,
+            ),
+        }
+    }
+}
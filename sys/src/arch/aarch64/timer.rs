@@ -1,13 +1,16 @@
 //! Architectural timer primitives.
 
-use aarch64_cpu::{asm::barrier, registers::CNTPCT_EL0};
+use aarch64_cpu::{
+    asm::barrier,
+    registers::{CNTPCT_EL0, CNTP_CTL_EL0, CNTP_CVAL_EL0},
+};
 use core::{
     num::{NonZeroU128, NonZeroU32, NonZeroU64},
     ops::{Add, Div},
     time::Duration,
     u64,
 };
-use tock_registers::interfaces::Readable;
+use tock_registers::interfaces::{Readable, Writeable};
 
 use crate::warn;
 
@@ -112,3 +115,18 @@ pub fn spin_for(duration: Duration) {
 
     while GenericTimerCounterValue(CNTPCT_EL0.get()) < counter_value_target {}
 }
+
+/// Let the timer interrupt fire once the uptime reaches `deadline`. Deadlines in the past fire
+/// right away.
+pub fn set_timer_irq(deadline: Duration) {
+    let counter_value: GenericTimerCounterValue =
+        deadline.try_into().unwrap_or(GenericTimerCounterValue::MAX);
+
+    CNTP_CVAL_EL0.set(counter_value.0);
+    CNTP_CTL_EL0.write(CNTP_CTL_EL0::ENABLE::SET + CNTP_CTL_EL0::IMASK::CLEAR);
+}
+
+/// Stop the timer interrupt.
+pub fn clear_timer_irq() {
+    CNTP_CTL_EL0.write(CNTP_CTL_EL0::ENABLE::CLEAR);
+}
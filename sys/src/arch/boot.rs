@@ -4,5 +4,7 @@ mod arch_boot;
 
 pub use arch_boot::BOOT_CORE_ID;
 
+pub(crate) use arch_boot::{_start_secondary, SECONDARY_CORE_STACKS};
+
 //#[path = "../drivers/cpu.rs"]
 //mod cpu;
@@ -1,15 +1,43 @@
 //! board decsription
 
-use crate::synchronization::{interface::Mutex, IrqSafeNullLock};
+use crate::{
+    cpu,
+    synchronization::{interface::ReadWriteEx, IrqSafeRwSpinLock},
+};
 
 pub mod interface {
+    use core::ops::RangeInclusive;
+
+    use crate::memory::mmu::TranslationDescriptor;
 
     /// Board information
     pub trait Info {
         fn board_name(&self) -> &'static str;
     }
 
-    pub trait All: Info {}
+    /// Board memory description
+    pub trait Memory {
+        /// The last (inclusive) address of the kernel's virtual address space.
+        fn virt_addr_space_end_inclusive(&self) -> usize;
+
+        /// Descriptors for the regions that are not normal cacheable DRAM.
+        fn virt_mem_layout(&self) -> &'static [TranslationDescriptor];
+
+        /// The page aligned virtual range `memory::mmio::mmio_remap()` maps device MMIO into. The
+        /// layout must keep it unmapped.
+        fn virt_mmio_remap_region(&self) -> RangeInclusive<usize>;
+    }
+
+    /// Board power control
+    pub trait Power {
+        /// Restart the board.
+        fn reboot(&self) -> !;
+
+        /// Turn the board off, or as close to off as it gets.
+        fn power_off(&self) -> !;
+    }
+
+    pub trait All: Info + Memory + Power {}
 }
 
 /// A placeholder.
@@ -21,19 +49,43 @@ impl interface::Info for NullBoard {
     }
 }
 
+impl interface::Memory for NullBoard {
+    fn virt_addr_space_end_inclusive(&self) -> usize {
+        0
+    }
+
+    fn virt_mem_layout(&self) -> &'static [crate::memory::mmu::TranslationDescriptor] {
+        &[]
+    }
+
+    fn virt_mmio_remap_region(&self) -> core::ops::RangeInclusive<usize> {
+        core::ops::RangeInclusive::new(1, 0)
+    }
+}
+
+impl interface::Power for NullBoard {
+    fn reboot(&self) -> ! {
+        cpu::wait_forever()
+    }
+
+    fn power_off(&self) -> ! {
+        cpu::wait_forever()
+    }
+}
+
 impl interface::All for NullBoard {}
 
 static NULL_BOARD: NullBoard = NullBoard {};
 
-static CURR_BOARD: IrqSafeNullLock<&'static (dyn interface::All + Sync)> =
-    IrqSafeNullLock::new(&NULL_BOARD);
+static CURR_BOARD: IrqSafeRwSpinLock<&'static (dyn interface::All + Sync)> =
+    IrqSafeRwSpinLock::new(&NULL_BOARD);
 
 /// Register a new board.
 pub fn register_board(new_board: &'static (dyn interface::All + Sync)) {
-    CURR_BOARD.lock(|brd| *brd = new_board);
+    CURR_BOARD.write(|brd| *brd = new_board);
 }
 
 /// Return a reference to the console.
 pub fn board() -> &'static dyn interface::All {
-    CURR_BOARD.lock(|brd| *brd)
+    CURR_BOARD.read(|brd| *brd)
 }
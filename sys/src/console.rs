@@ -1,4 +1,4 @@
-use crate::synchronization::{interface::ReadWriteEx, InitStateLock};
+use crate::synchronization::{interface::ReadWriteEx, IrqSafeRwSpinLock};
 
 /// Console interfaces.
 pub mod interface {
@@ -12,6 +12,11 @@ pub mod interface {
         /// Write a Rust format string.
         fn write_fmt(&self, args: fmt::Arguments) -> fmt::Result;
 
+        /// Write a Rust format string from a path that may have interrupted a write in progress
+        /// on the executing core, like a panic. Must not wait forever for locks the console
+        /// holds, even if that garbles the output.
+        fn force_write_fmt(&self, args: fmt::Arguments) -> fmt::Result;
+
         /// Block until the last buffered character has been physically put on the TX wire.
         fn flush(&self);
     }
@@ -54,6 +59,10 @@ impl interface::Write for NullConsole {
         core::fmt::Result::Ok(())
     }
 
+    fn force_write_fmt(&self, _args: core::fmt::Arguments) -> core::fmt::Result {
+        core::fmt::Result::Ok(())
+    }
+
     fn flush(&self) {}
 }
 
@@ -67,8 +76,8 @@ impl interface::All for NullConsole {}
 
 static NULL_CONSOLE: NullConsole = NullConsole {};
 
-static CURR_CONSOLE: InitStateLock<&'static (dyn interface::All + Sync)> =
-    InitStateLock::new(&NULL_CONSOLE);
+static CURR_CONSOLE: IrqSafeRwSpinLock<&'static (dyn interface::All + Sync)> =
+    IrqSafeRwSpinLock::new(&NULL_CONSOLE);
 
 /// Register a new console.
 pub fn register_console(new_console: &'static (dyn interface::All + Sync)) {
@@ -6,6 +6,9 @@ pub use boot::BOOT_CORE_ID;
 #[path = "arch/aarch64/cpu.rs"]
 mod arch_cpu;
 
+pub mod percpu;
 pub mod smp;
 
-pub use arch_cpu::{nop, wait_forever};
+pub use arch_cpu::{
+    clean_invalidate_data_cache, nop, sev, sync_instruction_cache, wait_forever, wfi,
+};
@@ -0,0 +1,101 @@
+//! Per-core data.
+//!
+//! Each core's TPIDR_EL1 points at a block describing the core, set up by `init_local()` before
+//! anything else runs on it. A `PerCpu` keeps one value per core and picks the executing core's
+//! through that block.
+//!
+//! A core's value is only handed out while IRQs are masked, so neither an IRQ handler nor a thread
+//! switch gets in between. Values are shared with the other cores only by `iter()`, and only if
+//! they are `Sync`.
+
+#[cfg(target_arch = "aarch64")]
+#[path = "../arch/aarch64/cpu/percpu.rs"]
+mod arch_percpu;
+
+use core::slice;
+
+use super::smp::{self, NUM_CORES};
+use crate::exception::asynchronous::{self, IrqContext};
+
+/// The block TPIDR_EL1 points at.
+struct CpuBlock {
+    core_id: usize,
+}
+
+static CPU_BLOCKS: [CpuBlock; NUM_CORES] = {
+    let mut blocks = [const { CpuBlock { core_id: 0 } }; NUM_CORES];
+
+    let mut i = 0;
+    while i < NUM_CORES {
+        blocks[i].core_id = i;
+        i += 1;
+    }
+
+    blocks
+};
+
+/// Point the executing core at its block.
+///
+/// # Safety
+///
+/// - Must be called on every core before it uses a `PerCpu`, as TPIDR_EL1 is undefined out of
+///   reset.
+pub(crate) unsafe fn init_local() {
+    arch_percpu::set_local_block(&CPU_BLOCKS[smp::core_id::<usize>()] as *const CpuBlock as usize);
+}
+
+/// Return the index of the executing core's values.
+#[inline(always)]
+fn local_core_id() -> usize {
+    let block = arch_percpu::local_block() as *const CpuBlock;
+
+    unsafe { (*block).core_id }
+}
+
+/// One `T` per core.
+///
+/// Values are only handed out by shared reference, so changing them takes a `Cell` or an atomic.
+pub struct PerCpu<T> {
+    data: [T; NUM_CORES],
+}
+
+unsafe impl<T> Sync for PerCpu<T> where T: Send {}
+
+impl<T> PerCpu<T> {
+    /// Create an instance with every core's value set to `init`.
+    pub const fn new(init: T) -> Self
+    where
+        T: Copy,
+    {
+        Self {
+            data: [init; NUM_CORES],
+        }
+    }
+
+    /// Create an instance from the values of all cores, indexed by core ID.
+    pub const fn from_array(data: [T; NUM_CORES]) -> Self {
+        Self { data }
+    }
+
+    /// Return the executing core's value, from an IRQ handler.
+    pub fn get<'irq_context>(
+        &'irq_context self,
+        _ic: &IrqContext<'irq_context>,
+    ) -> &'irq_context T {
+        &self.data[local_core_id()]
+    }
+
+    /// Run `f` on the executing core's value with IRQs masked.
+    pub fn with<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        asynchronous::exec_with_irq_masked(|| f(&self.data[local_core_id()]))
+    }
+
+    /// Return the values of all cores, indexed by core ID, e.g. for reporting. Cores that are not
+    /// online keep their initial value.
+    pub fn iter(&self) -> slice::Iter<'_, T>
+    where
+        T: Sync,
+    {
+        self.data.iter()
+    }
+}
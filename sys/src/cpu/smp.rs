@@ -4,4 +4,124 @@
 #[path = "../arch/aarch64/cpu/smp.rs"]
 mod arch_smp;
 
-pub use arch_smp::core_id;
+use core::{
+    mem,
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use crate::{
+    cpu::{self, boot},
+    memory::{phys_to_virt, virt_to_phys, KERNEL_STACK_SIZE},
+    scheduler::KernelStack,
+    state,
+    timer_manager::timer_manager,
+};
+
+pub use arch_smp::{core_id, NUM_CORES};
+
+/// How long a released core gets to finish its init.
+const CORE_ONLINE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// One bit per secondary core that finished its init.
+static SECONDARY_CORES_ONLINE: AtomicUsize = AtomicUsize::new(0);
+
+/// Return whether a core is up and running. The boot core always is.
+pub fn is_core_online(core_id: usize) -> bool {
+    core_id == boot::BOOT_CORE_ID as usize
+        || SECONDARY_CORES_ONLINE.load(Ordering::Acquire) & (1 << core_id) != 0
+}
+
+/// Return the number of cores up and running, the boot core included.
+pub fn num_cores_online() -> usize {
+    (0..NUM_CORES).filter(|x| is_core_online(*x)).count()
+}
+
+/// Called by a secondary core once its init is done.
+pub(crate) fn set_core_online() {
+    SECONDARY_CORES_ONLINE.fetch_or(1 << core_id::<usize>(), Ordering::Release);
+}
+
+/// Release a core from the spin table and wait for it to come online.
+unsafe fn start_secondary_core(
+    core_id: usize,
+    phys_release_addr: usize,
+) -> Result<(), &'static str> {
+    if core_id >= NUM_CORES || core_id == boot::BOOT_CORE_ID as usize {
+        return Err("Not a secondary core");
+    }
+
+    if is_core_online(core_id) {
+        return Err("Core already online");
+    }
+
+    // The core writes to its stack before its caches are on, so no dirty line of the stack may be
+    // written back over that later.
+    let stack = KernelStack::new()?;
+    cpu::clean_invalidate_data_cache(stack.top() - KERNEL_STACK_SIZE, KERNEL_STACK_SIZE);
+
+    let stack_slot = &boot::SECONDARY_CORE_STACKS[core_id];
+    stack_slot.store(stack.phys_top() as u64, Ordering::Relaxed);
+    cpu::clean_invalidate_data_cache(
+        stack_slot as *const AtomicU64 as usize,
+        mem::size_of::<AtomicU64>(),
+    );
+
+    // The core keeps running on it, even if it comes up too late.
+    mem::forget(stack);
+
+    // The firmware polls the release address with caching off, and waits in wfe in between.
+    let release = phys_to_virt(phys_release_addr) as *mut u64;
+    core::ptr::write_volatile(
+        release,
+        virt_to_phys(boot::_start_secondary as *const () as usize) as u64,
+    );
+    cpu::clean_invalidate_data_cache(release as usize, mem::size_of::<u64>());
+    cpu::sev();
+
+    let timers = timer_manager();
+    let start = timers.uptime();
+    while !is_core_online(core_id) {
+        if timers.uptime() - start > CORE_ONLINE_TIMEOUT {
+            return Err("Core did not come online");
+        }
+
+        cpu::nop();
+    }
+
+    Ok(())
+}
+
+/// Bring up the secondary cores the firmware parked in a spin table, given as pairs of the core's
+/// ID and the physical address of its release slot. Each core gets a kernel stack of its own,
+/// drops from EL2 to EL1 and turns on the MMU like the boot core, then calls the board's
+/// `board_secondary_init()`.
+///
+/// The kernel transitions to `MultiCoreMain` once any core came online. The first error is
+/// returned, but cores after a failing one are still tried.
+///
+/// # Safety
+///
+/// - The addresses must be the release addresses the firmware polls.
+pub unsafe fn start_secondary_cores(spin_table: &[(usize, usize)]) -> Result<(), &'static str> {
+    let state_manager = state::state_manager();
+
+    if !state_manager.is_single_core_main() {
+        return Err("Secondary cores can only be started in SingleCoreMain");
+    }
+
+    let mut result = Ok(());
+    for (core_id, phys_release_addr) in spin_table {
+        let started = start_secondary_core(*core_id, *phys_release_addr);
+
+        if result.is_ok() {
+            result = started;
+        }
+    }
+
+    if num_cores_online() > 1 {
+        state_manager.transition_to_multi_core_main();
+    }
+
+    result
+}
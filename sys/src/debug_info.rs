@@ -2,27 +2,17 @@
 #[macro_export]
 macro_rules! info {
     () => {
-        let timestamp = $crate::timer_manager::timer_manager().uptime();
-        $crate::println!(
-            "[I {:>3}.{:06}] ",
-            timestamp.as_secs(),
-            timestamp.subsec_micros(),
-        );
+        let timestamp = $crate::timer_manager::timer_manager().log_timestamp();
+        $crate::println!("[I {}] ", timestamp);
     };
     ($fmt:expr) => {
-        let timestamp = $crate::timer_manager::timer_manager().uptime();
-        $crate::println!(
-            "[I {:>3}.{:06}] {}",
-            timestamp.as_secs(),
-            timestamp.subsec_micros(),
-            $fmt
-        );
+        let timestamp = $crate::timer_manager::timer_manager().log_timestamp();
+        $crate::println!("[I {}] {}", timestamp, $fmt);
     };
     ($fmt:expr, $($arg:tt)*) => {
-        let timestamp = $crate::timer_manager::timer_manager().uptime();
-        ($crate::println!(concat!("[I {:>3}.{:06}] ", $fmt),
-            timestamp.as_secs(),
-            timestamp.subsec_micros(),
+        let timestamp = $crate::timer_manager::timer_manager().log_timestamp();
+        ($crate::println!(concat!("[I {}] ", $fmt),
+            timestamp,
             $($arg)*));
     };
 }
@@ -31,27 +21,17 @@ macro_rules! info {
 #[macro_export]
 macro_rules! warn {
     () => {
-        let timestamp = $crate::timer_manager::timer_manager().uptime();
-        $crate::println!(
-            "[W {:>3}.{:06}] ",
-            timestamp.as_secs(),
-            timestamp.subsec_micros(),
-        );
+        let timestamp = $crate::timer_manager::timer_manager().log_timestamp();
+        $crate::println!("[W {}] ", timestamp);
     };
     ($fmt:expr) => {
-        let timestamp = $crate::timer_manager::timer_manager().uptime();
-        $crate::println!(
-            "[W {:>3}.{:06}] {}",
-            timestamp.as_secs(),
-            timestamp.subsec_micros(),
-            $fmt
-        );
+        let timestamp = $crate::timer_manager::timer_manager().log_timestamp();
+        $crate::println!("[W {}] {}", timestamp, $fmt);
     };
     ($fmt:expr, $($arg:tt)*) => {
-        let timestamp = $crate::timer_manager::timer_manager().uptime();
-        ($crate::println!(concat!("[W {:>3}.{:06}] ", $fmt),
-            timestamp.as_secs(),
-            timestamp.subsec_micros(),
+        let timestamp = $crate::timer_manager::timer_manager().log_timestamp();
+        ($crate::println!(concat!("[W {}] ", $fmt),
+            timestamp,
             $($arg)*));
     };
 }
@@ -5,7 +5,7 @@ use crate::{
     synchronization::{interface::ReadWriteEx, InitStateLock},
 };
 
-const NUM_DRIVERS: usize = 5;
+const NUM_DRIVERS: usize = 8;
 
 /// Driver interfaces.
 pub mod interface {
@@ -51,6 +51,9 @@ impl driver_manager::interface::DeviceDriver for GicV2 {
     }
 
     unsafe fn init(&self) -> Result<(), &'static str> {
+        self.gicd.map()?;
+        self.gicc.map()?;
+
         if crate::cpu::BOOT_CORE_ID == crate::cpu::smp::core_id() {
             self.gicd.boot_core_init();
         }
@@ -115,6 +118,16 @@ impl exception::asynchronous::interface::IrqManager for GicV2 {
     fn print_handler(&self) {
         use crate::info;
 
+        info!("    Local handler:");
+
+        self.handler_table.read(|table| {
+            for (i, opt) in table.iter().take(32).enumerate() {
+                if let Some(handler) = opt {
+                    info!("        {: >3}. {}", i, handler.name());
+                }
+            }
+        });
+
         info!("    Peripheral handler:");
 
         self.handler_table.read(|table| {
@@ -60,6 +60,11 @@ impl GicC {
         }
     }
 
+    /// Map the registers.
+    pub fn map(&self) -> Result<(), &'static str> {
+        self.registers.map()
+    }
+
     /// Accept interrupts of any priority.
     /// # Safety
     pub fn priority_accept_all(&self) {
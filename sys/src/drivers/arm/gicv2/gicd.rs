@@ -9,7 +9,7 @@ use tock_registers::{
 use crate::{
     drivers::common::MmioDerefWrapper,
     state,
-    synchronization::{interface::Mutex, IrqSafeNullLock},
+    synchronization::{interface::Mutex, IrqSafeSpinLock},
 };
 
 register_bitfields! {
@@ -86,7 +86,7 @@ type BankedRegisters = MmioDerefWrapper<BankedRegisterBlock>;
 /// Representation of the GIC Distributor.
 pub struct GicD {
     /// Access to shared registers is guarded with a lock.
-    shared_registers: IrqSafeNullLock<SharedRegisters>,
+    shared_registers: IrqSafeSpinLock<SharedRegisters>,
 
     /// Access to banked registers is unguarded.
     banked_registers: BankedRegisters,
@@ -97,11 +97,17 @@ impl GicD {
     /// # Safety
     pub const unsafe fn new(mmio_base_addr: usize) -> Self {
         Self {
-            shared_registers: IrqSafeNullLock::new(SharedRegisters::new(mmio_base_addr)),
+            shared_registers: IrqSafeSpinLock::new(SharedRegisters::new(mmio_base_addr)),
             banked_registers: BankedRegisters::new(mmio_base_addr),
         }
     }
 
+    /// Map the registers.
+    pub fn map(&self) -> Result<(), &'static str> {
+        self.shared_registers.lock(|regs| regs.map())?;
+        self.banked_registers.map()
+    }
+
     /// Use a banked ITARGETSR to retrieve the executing core's GIC target mask.
     fn local_gic_target_mask(&self) -> u32 {
         self.banked_registers.itargetsr[0].read(ITARGETSR::Offset0)
@@ -1,27 +1,61 @@
 //! Common device driver code.
 
-use core::{fmt, marker::PhantomData, ops};
+use core::{
+    fmt,
+    marker::PhantomData,
+    mem, ops,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
+use crate::{memory::mmio, synchronization::IrqSafeSpinLock};
+
+/// Taken by `MmioDerefWrapper::map()`, so the same registers are never mapped twice. MMIO mappings
+/// are never given back, so a second one would leak window space.
+static MAP_LOCK: IrqSafeSpinLock<()> = IrqSafeSpinLock::new(());
+
+/// Access to a block of MMIO registers. Drivers call `map()` from their init, before the first
+/// register access. Accessing the registers earlier panics.
 pub struct MmioDerefWrapper<T> {
-    base_addr: usize,
+    phys_base_addr: usize,
+    virt_base_addr: AtomicUsize,
     phantom: PhantomData<fn() -> T>,
 }
 
 impl<T> MmioDerefWrapper<T> {
-    /// Create an instance.
-    pub const unsafe fn new(base_addr: usize) -> Self {
+    /// Create an instance for the registers at a physical address.
+    /// # Safety
+    pub const unsafe fn new(phys_base_addr: usize) -> Self {
         Self {
-            base_addr,
+            phys_base_addr,
+            virt_base_addr: AtomicUsize::new(0),
             phantom: PhantomData,
         }
     }
+
+    /// Map the registers through `mmio::mmio_remap()`, unless they are mapped already.
+    pub fn map(&self) -> Result<(), &'static str> {
+        let _guard = MAP_LOCK.lock_guard();
+
+        if self.virt_base_addr.load(Ordering::Acquire) != 0 {
+            return Ok(());
+        }
+
+        let mapping = unsafe { mmio::mmio_remap(self.phys_base_addr, mem::size_of::<T>())? };
+        self.virt_base_addr
+            .store(mapping.virt_addr(), Ordering::Release);
+
+        Ok(())
+    }
 }
 
 impl<T> ops::Deref for MmioDerefWrapper<T> {
     type Target = T;
 
     fn deref(&self) -> &Self::Target {
-        unsafe { &*(self.base_addr as *const _) }
+        let virt_base_addr = self.virt_base_addr.load(Ordering::Relaxed);
+        assert!(virt_base_addr != 0, "MMIO registers accessed before map()");
+
+        unsafe { &*(virt_base_addr as *const _) }
     }
 }
 
@@ -3,9 +3,13 @@
 mod arch_exception;
 
 pub mod asynchronous;
+pub mod fixup;
+pub mod syscall;
 
 pub use arch_exception::{current_privilege_level, handling_init};
 
+pub(crate) use arch_exception::{prepare_thread_context, yield_to_scheduler};
+
 /// Privilege levels.
 #[derive(Eq, PartialEq)]
 pub enum PrivilegeLevel {
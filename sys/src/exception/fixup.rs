@@ -0,0 +1,105 @@
+//! Exception table style fixups for kernel instructions that are allowed to fault.
+
+use core::ops::RangeInclusive;
+
+use crate::synchronization::{interface::ReadWriteEx, InitStateLock};
+
+const NUM_FIXUPS: usize = 8;
+
+/// Lets kernel instructions that are expected to fault, e.g. when probing memory, resume at a
+/// fixup address instead of panicking.
+#[derive(Copy, Clone)]
+pub struct FixupDescriptor {
+    name: &'static str,
+    insn_start: usize,
+    insn_end_inclusive: usize,
+    fixup_addr: usize,
+}
+
+impl FixupDescriptor {
+    /// Create an instance. A fault of any instruction in `insn_range` resumes at `fixup_addr`.
+    pub fn new(name: &'static str, insn_range: RangeInclusive<usize>, fixup_addr: usize) -> Self {
+        Self {
+            name,
+            insn_start: *insn_range.start(),
+            insn_end_inclusive: *insn_range.end(),
+            fixup_addr,
+        }
+    }
+
+    /// The name of the fixup.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// The address execution resumes at.
+    pub fn fixup_addr(&self) -> usize {
+        self.fixup_addr
+    }
+
+    #[inline(always)]
+    fn covers(&self, insn_addr: usize) -> bool {
+        (self.insn_start..=self.insn_end_inclusive).contains(&insn_addr)
+    }
+}
+
+struct FixupManagerInner {
+    next_index: usize,
+    descriptors: [Option<FixupDescriptor>; NUM_FIXUPS],
+}
+
+impl FixupManagerInner {
+    pub const fn new() -> Self {
+        Self {
+            next_index: 0,
+            descriptors: [None; NUM_FIXUPS],
+        }
+    }
+}
+
+/// Keeps the registered fixups.
+pub struct FixupManager {
+    inner: InitStateLock<FixupManagerInner>,
+}
+
+impl FixupManager {
+    /// Create an instance.
+    pub const fn new() -> Self {
+        Self {
+            inner: InitStateLock::new(FixupManagerInner::new()),
+        }
+    }
+
+    /// Register a fixup with the kernel.
+    pub fn register_fixup(&self, descriptor: FixupDescriptor) -> Result<(), &'static str> {
+        self.inner.write(|inner| {
+            if inner.next_index >= NUM_FIXUPS {
+                return Err("No free fixup slot");
+            }
+
+            inner.descriptors[inner.next_index] = Some(descriptor);
+            inner.next_index += 1;
+
+            Ok(())
+        })
+    }
+
+    /// Return the fixup covering a faulting instruction, if any.
+    pub fn search(&self, insn_addr: usize) -> Option<FixupDescriptor> {
+        self.inner.read(|inner| {
+            inner
+                .descriptors
+                .iter()
+                .filter_map(|x| x.as_ref())
+                .find(|descriptor| descriptor.covers(insn_addr))
+                .copied()
+        })
+    }
+}
+
+static FIXUP_MANAGER: FixupManager = FixupManager::new();
+
+/// Return a reference to the fixup manager.
+pub fn fixup_manager() -> &'static FixupManager {
+    &FIXUP_MANAGER
+}
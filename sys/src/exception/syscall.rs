@@ -0,0 +1,108 @@
+//! System calls, through which lower privilege levels request kernel services.
+
+use crate::synchronization::{interface::ReadWriteEx, InitStateLock};
+
+/// Syscall numbers range from 0 to `NUM_SYSCALLS - 1`.
+pub const NUM_SYSCALLS: usize = 64;
+
+/// Returned to the caller of a syscall number without a handler.
+pub const SYSCALL_UNKNOWN: u64 = u64::MAX;
+
+/// The arguments of a syscall.
+pub type SyscallArgs = [u64; 6];
+
+/// A syscall handler. The return value is handed back to the caller.
+pub type SyscallHandler = fn(&SyscallArgs) -> u64;
+
+/// Syscall descriptor.
+#[derive(Copy, Clone)]
+pub struct SyscallDescriptor {
+    number: usize,
+    name: &'static str,
+    handler: SyscallHandler,
+}
+
+impl SyscallDescriptor {
+    /// Create an instance.
+    pub const fn new(number: usize, name: &'static str, handler: SyscallHandler) -> Self {
+        Self {
+            number,
+            name,
+            handler,
+        }
+    }
+
+    /// Return the number.
+    pub const fn number(&self) -> usize {
+        self.number
+    }
+
+    /// Return the name.
+    pub const fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+/// Dispatches syscalls to the registered handlers. Writable only during kernel init.
+pub struct SyscallTable {
+    inner: InitStateLock<[Option<SyscallDescriptor>; NUM_SYSCALLS]>,
+}
+
+impl SyscallTable {
+    /// Create an instance.
+    pub const fn new() -> Self {
+        Self {
+            inner: InitStateLock::new([None; NUM_SYSCALLS]),
+        }
+    }
+
+    /// Register a syscall handler.
+    pub fn register_syscall(&self, descriptor: SyscallDescriptor) -> Result<(), &'static str> {
+        self.inner.write(|table| {
+            let slot = table
+                .get_mut(descriptor.number)
+                .ok_or("Syscall number out of range")?;
+
+            if slot.is_some() {
+                return Err("Syscall already registered");
+            }
+
+            *slot = Some(descriptor);
+
+            Ok(())
+        })
+    }
+
+    /// Call the handler of a syscall number. Returns `SYSCALL_UNKNOWN` if there is none.
+    pub fn dispatch(&self, number: u64, args: &SyscallArgs) -> u64 {
+        let handler = self.inner.read(|table| {
+            table
+                .get(number as usize)
+                .and_then(|x| x.as_ref())
+                .map(|descriptor| descriptor.handler)
+        });
+
+        match handler {
+            Some(handler) => handler(args),
+            None => SYSCALL_UNKNOWN,
+        }
+    }
+
+    /// Print the registered syscalls.
+    pub fn print_syscalls(&self) {
+        use crate::info;
+
+        self.inner.read(|table| {
+            for descriptor in table.iter().flatten() {
+                info!("      {: >3}. {}", descriptor.number(), descriptor.name());
+            }
+        });
+    }
+}
+
+static SYSCALL_TABLE: SyscallTable = SyscallTable::new();
+
+/// Return a reference to the syscall table.
+pub fn syscall_table() -> &'static SyscallTable {
+    &SYSCALL_TABLE
+}
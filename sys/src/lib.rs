@@ -1,6 +1,8 @@
 #![no_std]
 #![no_main]
 
+extern crate alloc;
+
 pub mod board;
 pub mod common;
 pub mod console;
@@ -9,26 +11,45 @@ pub mod debug_info;
 pub mod driver_manager;
 pub mod drivers;
 pub mod exception;
+pub mod memory;
 pub mod panic;
 pub mod print;
+pub mod scheduler;
 pub mod state;
 pub mod synchronization;
+pub mod task;
 pub mod timer_manager;
 
 // Callbacks for special board.
 extern "Rust" {
     fn board_early_init() -> Result<(), &'static str>;
 
+    fn board_secondary_init() -> Result<(), &'static str>;
+
     fn os_early_entry() -> !;
 }
 
 /// Early init code
 unsafe fn rpi_os_init() -> ! {
+    cpu::percpu::init_local();
+
     // Board init
     if let Err(x) = board_early_init() {
         panic!("Error initializing board: {}", x);
     }
 
+    if let Err(x) = memory::nofault::register_fixups() {
+        panic!("Error registering fixups: {}", x);
+    }
+
+    if let Err(x) = task::register_syscalls() {
+        panic!("Error registering syscalls: {}", x);
+    }
+
+    if let Err(x) = scheduler::scheduler().init() {
+        panic!("Error starting the scheduler: {}", x);
+    }
+
     // Init all drivers
     driver_manager::driver_manager().init_drivers_and_irqs();
 
@@ -40,3 +61,24 @@ unsafe fn rpi_os_init() -> ! {
 
     os_early_entry();
 }
+
+/// Init code of the secondary cores, released by `cpu::smp::start_secondary_cores()`.
+unsafe fn rpi_os_secondary_init() -> ! {
+    cpu::percpu::init_local();
+
+    if let Err(x) = board_secondary_init() {
+        panic!(
+            "Error initializing core {}: {}",
+            cpu::smp::core_id::<usize>(),
+            x
+        );
+    }
+
+    cpu::smp::set_core_online();
+
+    // The scheduler, the heap, the frame allocator, the kernel translation tables, the timers and
+    // the ASID allocator are still behind `IrqSafeNullLock`s, which only exclude the executing
+    // core. Until they aren't, this core stays out of the way with IRQs masked, so its GIC CPU
+    // interface never takes an IRQ.
+    cpu::wait_forever()
+}
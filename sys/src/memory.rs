@@ -0,0 +1,32 @@
+//! Memory Management.
+
+pub mod address_space;
+pub mod frame_alloc;
+pub mod heap_alloc;
+pub mod mmio;
+pub mod mmu;
+pub mod nofault;
+
+/// The kernel is linked and runs in the top of the virtual address space, which maps physical
+/// memory linearly at this offset through TTBR1. The board linker scripts must use the same value.
+pub const KERNEL_VIRT_OFFSET: usize = 0xffff_ffff_0000_0000;
+
+/// Return the kernel virtual address of a physical address.
+#[inline(always)]
+pub const fn phys_to_virt(phys_addr: usize) -> usize {
+    phys_addr + KERNEL_VIRT_OFFSET
+}
+
+/// Return the physical address of a kernel virtual address.
+#[inline(always)]
+pub const fn virt_to_phys(virt_addr: usize) -> usize {
+    virt_addr - KERNEL_VIRT_OFFSET
+}
+
+/// log2 of `KERNEL_STACK_SIZE`.
+pub const KERNEL_STACK_SHIFT: usize = 18;
+
+/// The size of a kernel stack. Each stack is the upper half of a naturally aligned block twice its
+/// size, so bit `KERNEL_STACK_SHIFT` is set for every address inside a stack and clear right below
+/// it. The exception entry relies on this to detect a stack overflow.
+pub const KERNEL_STACK_SIZE: usize = 1 << KERNEL_STACK_SHIFT;
@@ -0,0 +1,173 @@
+//! User address spaces.
+
+use alloc::vec::Vec;
+
+use crate::{
+    cpu,
+    memory::{
+        frame_alloc::{frame_allocator, FRAME_SIZE},
+        mmu::{
+            self, interface::Mmu, AttributeFields, UserTranslationTable, NUM_ASIDS,
+            USER_ADDR_SPACE_SIZE,
+        },
+        phys_to_virt,
+    },
+    synchronization::{interface::Mutex, IrqSafeNullLock},
+};
+
+const BITS_PER_WORD: usize = u64::BITS as usize;
+
+struct AsidAllocatorInner {
+    /// One bit per ASID. A set bit means the ASID is in use. ASID 0 stays reserved for "no user
+    /// address space".
+    bitmap: [u64; NUM_ASIDS / BITS_PER_WORD],
+}
+
+impl AsidAllocatorInner {
+    pub const fn new() -> Self {
+        let mut bitmap = [0; NUM_ASIDS / BITS_PER_WORD];
+        bitmap[0] = 1;
+
+        Self { bitmap }
+    }
+
+    fn alloc(&mut self) -> Option<u16> {
+        let asid = (0..NUM_ASIDS)
+            .find(|asid| self.bitmap[asid / BITS_PER_WORD] & (1 << (asid % BITS_PER_WORD)) == 0)?;
+        self.bitmap[asid / BITS_PER_WORD] |= 1 << (asid % BITS_PER_WORD);
+
+        Some(asid as u16)
+    }
+
+    fn free(&mut self, asid: u16) {
+        let asid = asid as usize;
+
+        self.bitmap[asid / BITS_PER_WORD] &= !(1 << (asid % BITS_PER_WORD));
+    }
+}
+
+static ASID_ALLOCATOR: IrqSafeNullLock<AsidAllocatorInner> =
+    IrqSafeNullLock::new(AsidAllocatorInner::new());
+
+/// The address space of a user task. Its translation table is tagged with an ASID of its own, so
+/// switching between address spaces doesn't need a TLB flush.
+pub struct AddressSpace {
+    asid: u16,
+    tables: UserTranslationTable,
+
+    /// Frames mapped by `map_new_pages()`. They are freed together with the address space.
+    frames: Vec<usize>,
+}
+
+impl AddressSpace {
+    /// Create an empty address space.
+    pub fn new() -> Result<Self, &'static str> {
+        let tables = UserTranslationTable::new()?;
+        let asid = ASID_ALLOCATOR
+            .lock(|inner| inner.alloc())
+            .ok_or("Out of ASIDs")?;
+
+        Ok(Self {
+            asid,
+            tables,
+            frames: Vec::new(),
+        })
+    }
+
+    /// The ASID of the address space.
+    pub fn asid(&self) -> u16 {
+        self.asid
+    }
+
+    /// Map `num_pages` freshly allocated and zeroed pages starting at `virt_addr`. The attributes
+    /// must use one of the user access permissions.
+    pub fn map_new_pages(
+        &mut self,
+        virt_addr: usize,
+        num_pages: usize,
+        attribute_fields: &AttributeFields,
+    ) -> Result<(), &'static str> {
+        if !attribute_fields.acc_perms.is_user() {
+            return Err("User pages need user access permissions");
+        }
+
+        let end_exclusive = num_pages
+            .checked_mul(FRAME_SIZE)
+            .and_then(|x| x.checked_add(virt_addr))
+            .ok_or("Virtual address range overflows")?;
+
+        if end_exclusive > USER_ADDR_SPACE_SIZE {
+            return Err("Virtual address out of range");
+        }
+
+        for i in 0..num_pages {
+            let phys_addr = frame_allocator().alloc_frame()?;
+            unsafe { core::ptr::write_bytes(phys_to_virt(phys_addr) as *mut u8, 0, FRAME_SIZE) };
+
+            if let Err(x) =
+                self.tables
+                    .map_pages(virt_addr + i * FRAME_SIZE, phys_addr, 1, attribute_fields)
+            {
+                frame_allocator().free_frames(phys_addr, 1)?;
+
+                return Err(x);
+            }
+
+            self.frames.push(phys_addr);
+        }
+
+        Ok(())
+    }
+
+    /// Return the physical address a user virtual address is mapped to, if any.
+    pub fn translate(&self, virt_addr: usize) -> Option<usize> {
+        self.tables.translate(virt_addr)
+    }
+
+    /// Copy `data` to the mapped user memory at `virt_addr`, through the kernel's linear mapping.
+    /// The instruction cache is synchronized, so copied code can be executed right away.
+    pub fn copy_to_user(&mut self, virt_addr: usize, data: &[u8]) -> Result<(), &'static str> {
+        let mut done = 0;
+
+        while done < data.len() {
+            let addr = virt_addr
+                .checked_add(done)
+                .ok_or("Virtual address range overflows")?;
+            let phys_addr = self.translate(addr).ok_or("User page is not mapped")?;
+            let len = (FRAME_SIZE - (addr % FRAME_SIZE)).min(data.len() - done);
+
+            let dst = phys_to_virt(phys_addr);
+            unsafe {
+                core::ptr::copy_nonoverlapping(data[done..].as_ptr(), dst as *mut u8, len);
+            }
+            cpu::sync_instruction_cache(dst, len);
+
+            done += len;
+        }
+
+        Ok(())
+    }
+
+    /// Switch the user half of the address space over to this one.
+    ///
+    /// # Safety
+    ///
+    /// - The address space must stay alive until `mmu().deactivate_user_table()` is called.
+    pub unsafe fn activate(&self) {
+        mmu::mmu().activate_user_table(self.tables.phys_base_address(), self.asid);
+    }
+}
+
+impl Drop for AddressSpace {
+    fn drop(&mut self) {
+        // The tables are freed after this, so no TLB may still hold their entries.
+        mmu::mmu().invalidate_asid(self.asid);
+        ASID_ALLOCATOR.lock(|inner| inner.free(self.asid));
+
+        for phys_addr in self.frames.iter() {
+            if let Err(x) = frame_allocator().free_frames(*phys_addr, 1) {
+                panic!("Freeing user page: {}", x);
+            }
+        }
+    }
+}
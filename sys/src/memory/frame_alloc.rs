@@ -0,0 +1,297 @@
+//! Physical page frame allocator.
+
+use core::ops::{Range, RangeInclusive};
+
+use crate::{
+    common, info,
+    memory::mmu::KernelGranule,
+    synchronization::{interface::Mutex, IrqSafeNullLock},
+    warn,
+};
+
+/// The size of a physical frame. Matches the translation granule.
+pub const FRAME_SIZE: usize = KernelGranule::SIZE;
+
+/// log2(FRAME_SIZE).
+pub const FRAME_SHIFT: usize = FRAME_SIZE.trailing_zeros() as usize;
+
+/// Frames above this physical address are not managed.
+const MAX_PHYS_ADDR_EXCLUSIVE: usize = 4 * 1024 * 1024 * 1024;
+
+const MAX_FRAMES: usize = MAX_PHYS_ADDR_EXCLUSIVE >> FRAME_SHIFT;
+
+const BITS_PER_WORD: usize = u64::BITS as usize;
+
+type Bitmap = [u64; MAX_FRAMES / BITS_PER_WORD];
+
+struct FrameAllocatorInner {
+    /// One bit per frame. A set bit means the frame is in use or does not exist.
+    bitmap: Bitmap,
+
+    /// One bit per frame. A set bit means the frame was handed to the allocator, so it may be
+    /// freed.
+    managed: Bitmap,
+
+    /// Number of frames handed to the allocator.
+    num_frames: usize,
+
+    /// Number of frames currently free.
+    num_free: usize,
+
+    /// Where to start searching next.
+    next_hint: usize,
+}
+
+/// A bitmap allocator for physical frames.
+pub struct FrameAllocator {
+    inner: IrqSafeNullLock<FrameAllocatorInner>,
+}
+
+/// Return the frame numbers completely contained in a physical range.
+fn frames_inside(range: &RangeInclusive<usize>) -> Range<usize> {
+    let first = range.start().div_ceil(FRAME_SIZE);
+    let last_exclusive = ((*range.end()).min(MAX_PHYS_ADDR_EXCLUSIVE - 1) + 1) >> FRAME_SHIFT;
+
+    first..last_exclusive
+}
+
+/// Return the frame numbers touched by a physical range.
+fn frames_touching(range: &RangeInclusive<usize>) -> Range<usize> {
+    let first = range.start() >> FRAME_SHIFT;
+    let last_exclusive = ((*range.end()).min(MAX_PHYS_ADDR_EXCLUSIVE - 1) >> FRAME_SHIFT) + 1;
+
+    first..last_exclusive
+}
+
+impl FrameAllocatorInner {
+    pub const fn new() -> Self {
+        Self {
+            bitmap: [u64::MAX; MAX_FRAMES / BITS_PER_WORD],
+            managed: [0; MAX_FRAMES / BITS_PER_WORD],
+            num_frames: 0,
+            num_free: 0,
+            next_hint: 0,
+        }
+    }
+
+    #[inline(always)]
+    fn bit(bitmap: &Bitmap, frame: usize) -> bool {
+        bitmap[frame / BITS_PER_WORD] & (1 << (frame % BITS_PER_WORD)) != 0
+    }
+
+    #[inline(always)]
+    fn set_bit(bitmap: &mut Bitmap, frame: usize, value: bool) {
+        let word = &mut bitmap[frame / BITS_PER_WORD];
+        let bit = 1 << (frame % BITS_PER_WORD);
+
+        if value {
+            *word |= bit;
+        } else {
+            *word &= !bit;
+        }
+    }
+
+    #[inline(always)]
+    fn is_used(&self, frame: usize) -> bool {
+        Self::bit(&self.bitmap, frame)
+    }
+
+    #[inline(always)]
+    fn set_used(&mut self, frame: usize, used: bool) {
+        Self::set_bit(&mut self.bitmap, frame, used);
+    }
+
+    #[inline(always)]
+    fn is_managed(&self, frame: usize) -> bool {
+        Self::bit(&self.managed, frame)
+    }
+
+    #[inline(always)]
+    fn set_managed(&mut self, frame: usize, managed: bool) {
+        Self::set_bit(&mut self.managed, frame, managed);
+    }
+
+    /// Seed the bitmap with usable DRAM, then take out the reserved ranges.
+    fn init(
+        &mut self,
+        dram_ranges: &[RangeInclusive<usize>],
+        reserved_ranges: &[RangeInclusive<usize>],
+    ) -> Result<(), &'static str> {
+        if self.num_frames != 0 {
+            return Err("Frame allocator already initialized");
+        }
+
+        for range in dram_ranges {
+            if *range.end() >= MAX_PHYS_ADDR_EXCLUSIVE {
+                warn!(
+                    "Frame allocator: Ignoring DRAM above {:#x}",
+                    MAX_PHYS_ADDR_EXCLUSIVE
+                );
+            }
+
+            for frame in frames_inside(range) {
+                if self.is_used(frame) {
+                    self.set_used(frame, false);
+                    self.set_managed(frame, true);
+                    self.num_frames += 1;
+                    self.num_free += 1;
+                }
+            }
+        }
+
+        for range in reserved_ranges {
+            for frame in frames_touching(range) {
+                if !self.is_used(frame) {
+                    self.set_used(frame, true);
+                    self.set_managed(frame, false);
+                    self.num_frames -= 1;
+                    self.num_free -= 1;
+                }
+            }
+        }
+
+        if self.num_free == 0 {
+            return Err("No free physical memory");
+        }
+
+        Ok(())
+    }
+
+    /// Find and claim `count` contiguous free frames, starting at a multiple of `align` frames.
+    fn alloc(&mut self, count: usize, align: usize) -> Option<usize> {
+        if count == 0 || count > self.num_free {
+            return None;
+        }
+
+        // Two passes over the possible first frames: from the hint to the end, then from the start
+        // to the hint.
+        for candidates in [self.next_hint..MAX_FRAMES, 0..self.next_hint] {
+            let mut first = candidates.start.next_multiple_of(align);
+
+            while first < candidates.end && first + count <= MAX_FRAMES {
+                match (first..first + count).find(|frame| self.is_used(*frame)) {
+                    None => {
+                        for frame in first..first + count {
+                            self.set_used(frame, true);
+                        }
+                        self.num_free -= count;
+                        self.next_hint = first + count;
+
+                        return Some(first);
+                    }
+                    Some(used) => first = (used + 1).next_multiple_of(align),
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Give back frames.
+    fn free(&mut self, first: usize, count: usize) -> Result<(), &'static str> {
+        if first + count > MAX_FRAMES {
+            return Err("Frame out of range");
+        }
+
+        // Frames the allocator doesn't own count as used, but must not become free.
+        if (first..first + count).any(|frame| !self.is_managed(frame)) {
+            return Err("Physical frame not managed by the allocator");
+        }
+
+        if (first..first + count).any(|frame| !self.is_used(frame)) {
+            return Err("Double free of physical frame");
+        }
+
+        for frame in first..first + count {
+            self.set_used(frame, false);
+        }
+        self.num_free += count;
+
+        Ok(())
+    }
+}
+
+impl FrameAllocator {
+    /// Create an instance.
+    pub const fn new() -> Self {
+        Self {
+            inner: IrqSafeNullLock::new(FrameAllocatorInner::new()),
+        }
+    }
+
+    /// Hand the board's DRAM to the allocator. Frames overlapping any of the reserved ranges are
+    /// never handed out.
+    pub fn init(
+        &self,
+        dram_ranges: &[RangeInclusive<usize>],
+        reserved_ranges: &[RangeInclusive<usize>],
+    ) -> Result<(), &'static str> {
+        self.inner
+            .lock(|inner| inner.init(dram_ranges, reserved_ranges))
+    }
+
+    /// Allocate a single frame and return its physical address.
+    pub fn alloc_frame(&self) -> Result<usize, &'static str> {
+        self.alloc_frames(1)
+    }
+
+    /// Allocate `count` physically contiguous frames and return the physical address of the
+    /// first one.
+    pub fn alloc_frames(&self, count: usize) -> Result<usize, &'static str> {
+        self.alloc_frames_aligned(count, 1)
+    }
+
+    /// Allocate `count` physically contiguous frames, starting at a physical address aligned to
+    /// `align_frames` frames. Useful for DMA buffers with alignment constraints.
+    pub fn alloc_frames_aligned(
+        &self,
+        count: usize,
+        align_frames: usize,
+    ) -> Result<usize, &'static str> {
+        if !align_frames.is_power_of_two() {
+            return Err("Alignment must be a power of two");
+        }
+
+        self.inner
+            .lock(|inner| inner.alloc(count, align_frames))
+            .map(|first| first << FRAME_SHIFT)
+            .ok_or("Out of physical frames")
+    }
+
+    /// Free frames previously returned by one of the alloc functions.
+    pub fn free_frames(&self, phys_addr: usize, count: usize) -> Result<(), &'static str> {
+        if !phys_addr.is_multiple_of(FRAME_SIZE) {
+            return Err("Address is not frame aligned");
+        }
+
+        self.inner
+            .lock(|inner| inner.free(phys_addr >> FRAME_SHIFT, count))
+    }
+
+    /// Print the frame usage statistics.
+    pub fn print_usage(&self) {
+        self.inner.lock(|inner| {
+            let (total, total_unit) =
+                common::size_human_readable_ceil(inner.num_frames << FRAME_SHIFT);
+            let (free, free_unit) = common::size_human_readable_ceil(inner.num_free << FRAME_SHIFT);
+
+            info!(
+                "      {} frames of {} KiB, {} free | {: >3} {} total, {: >3} {} free",
+                inner.num_frames,
+                FRAME_SIZE / 1024,
+                inner.num_free,
+                total,
+                total_unit,
+                free,
+                free_unit
+            );
+        });
+    }
+}
+
+static KERNEL_FRAME_ALLOCATOR: FrameAllocator = FrameAllocator::new();
+
+/// Return a reference to the kernel's physical frame allocator.
+pub fn frame_allocator() -> &'static FrameAllocator {
+    &KERNEL_FRAME_ALLOCATOR
+}
@@ -0,0 +1,320 @@
+//! Kernel heap allocator.
+
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    mem,
+    ops::RangeInclusive,
+    ptr,
+};
+
+use crate::{
+    common, info,
+    synchronization::{interface::Mutex, IrqSafeNullLock},
+    warn,
+};
+
+/// Header of a free block. Free blocks form a singly linked list sorted by address.
+#[repr(C, align(16))]
+struct FreeBlock {
+    size: usize,
+    next: *mut FreeBlock,
+}
+
+impl FreeBlock {
+    /// The smallest block the allocator can track. Every block size and address is a multiple of
+    /// this.
+    const MIN_SIZE: usize = mem::size_of::<FreeBlock>();
+
+    #[inline(always)]
+    fn start_addr(&self) -> usize {
+        self as *const _ as usize
+    }
+
+    #[inline(always)]
+    fn end_addr_exclusive(&self) -> usize {
+        self.start_addr() + self.size
+    }
+}
+
+struct HeapAllocatorInner {
+    /// The first free block.
+    head: *mut FreeBlock,
+
+    /// The managed region.
+    heap_region: Option<RangeInclusive<usize>>,
+
+    /// Bytes currently handed out.
+    used: usize,
+
+    /// High watermark of `used`.
+    peak: usize,
+
+    /// Number of successful allocations.
+    num_allocs: usize,
+
+    /// Number of deallocations.
+    num_frees: usize,
+
+    /// Number of failed allocations.
+    num_failures: usize,
+}
+
+unsafe impl Send for HeapAllocatorInner {}
+
+/// A first-fit, address-ordered free list allocator.
+pub struct HeapAllocator {
+    inner: IrqSafeNullLock<HeapAllocatorInner>,
+}
+
+/// Round `addr` up to the next multiple of `align`.
+#[inline(always)]
+const fn align_up(addr: usize, align: usize) -> usize {
+    (addr + align - 1) & !(align - 1)
+}
+
+/// Round `addr` down to the previous multiple of `align`.
+#[inline(always)]
+const fn align_down(addr: usize, align: usize) -> usize {
+    addr & !(align - 1)
+}
+
+/// Return the size and alignment the allocator actually uses for a layout.
+#[inline(always)]
+fn block_layout(layout: &Layout) -> (usize, usize) {
+    let size = align_up(layout.size().max(1), FreeBlock::MIN_SIZE);
+    let align = layout.align().max(mem::align_of::<FreeBlock>());
+
+    (size, align)
+}
+
+impl HeapAllocatorInner {
+    pub const fn new() -> Self {
+        Self {
+            head: ptr::null_mut(),
+            heap_region: None,
+            used: 0,
+            peak: 0,
+            num_allocs: 0,
+            num_frees: 0,
+            num_failures: 0,
+        }
+    }
+
+    /// Hand the region to the allocator.
+    unsafe fn init(&mut self, heap_region: RangeInclusive<usize>) -> Result<(), &'static str> {
+        if self.heap_region.is_some() {
+            return Err("Heap already initialized");
+        }
+
+        let start = align_up(*heap_region.start(), FreeBlock::MIN_SIZE);
+        let end_exclusive = align_down(*heap_region.end() + 1, FreeBlock::MIN_SIZE);
+        if end_exclusive <= start {
+            return Err("Heap region too small");
+        }
+
+        self.heap_region = Some(start..=(end_exclusive - 1));
+        self.insert_free_block(start, end_exclusive - start);
+
+        Ok(())
+    }
+
+    /// Put a block back into the free list, merging it with adjacent free blocks.
+    unsafe fn insert_free_block(&mut self, addr: usize, size: usize) {
+        let mut prev: *mut FreeBlock = ptr::null_mut();
+        let mut curr = self.head;
+
+        while !curr.is_null() && (*curr).start_addr() < addr {
+            prev = curr;
+            curr = (*curr).next;
+        }
+
+        let block = addr as *mut FreeBlock;
+        block.write(FreeBlock { size, next: curr });
+
+        // Merge with the following block.
+        if !curr.is_null() && (*block).end_addr_exclusive() == (*curr).start_addr() {
+            (*block).size += (*curr).size;
+            (*block).next = (*curr).next;
+        }
+
+        if prev.is_null() {
+            self.head = block;
+        } else if (*prev).end_addr_exclusive() == addr {
+            // Merge with the preceding block.
+            (*prev).size += (*block).size;
+            (*prev).next = (*block).next;
+        } else {
+            (*prev).next = block;
+        }
+    }
+
+    /// Carve an allocation out of the first block that fits.
+    unsafe fn alloc(&mut self, layout: &Layout) -> *mut u8 {
+        let (size, align) = block_layout(layout);
+
+        let mut prev: *mut FreeBlock = ptr::null_mut();
+        let mut curr = self.head;
+
+        while !curr.is_null() {
+            let block_start = (*curr).start_addr();
+            let block_end_exclusive = (*curr).end_addr_exclusive();
+            let next = (*curr).next;
+
+            let alloc_start = align_up(block_start, align);
+            let alloc_end_exclusive = alloc_start.saturating_add(size);
+
+            if alloc_end_exclusive <= block_end_exclusive {
+                // Unlink the block, then return the unused head and tail to the list. Both are
+                // multiples of `FreeBlock::MIN_SIZE`, so they can always hold a header.
+                if prev.is_null() {
+                    self.head = next;
+                } else {
+                    (*prev).next = next;
+                }
+
+                if alloc_start > block_start {
+                    self.insert_free_block(block_start, alloc_start - block_start);
+                }
+                if block_end_exclusive > alloc_end_exclusive {
+                    self.insert_free_block(
+                        alloc_end_exclusive,
+                        block_end_exclusive - alloc_end_exclusive,
+                    );
+                }
+
+                self.used += size;
+                self.peak = self.peak.max(self.used);
+                self.num_allocs += 1;
+
+                return alloc_start as *mut u8;
+            }
+
+            prev = curr;
+            curr = next;
+        }
+
+        self.num_failures += 1;
+
+        ptr::null_mut()
+    }
+
+    /// Return an allocation to the free list.
+    unsafe fn dealloc(&mut self, ptr: *mut u8, layout: &Layout) {
+        let (size, _) = block_layout(layout);
+
+        self.insert_free_block(ptr as usize, size);
+
+        self.used -= size;
+        self.num_frees += 1;
+    }
+
+    /// Return the number of free blocks and the size of the largest one.
+    fn free_list_stats(&self) -> (usize, usize) {
+        let mut num_blocks = 0;
+        let mut largest = 0;
+        let mut curr = self.head;
+
+        while !curr.is_null() {
+            unsafe {
+                num_blocks += 1;
+                largest = largest.max((*curr).size);
+                curr = (*curr).next;
+            }
+        }
+
+        (num_blocks, largest)
+    }
+}
+
+impl HeapAllocator {
+    /// Create an instance.
+    pub const fn new() -> Self {
+        Self {
+            inner: IrqSafeNullLock::new(HeapAllocatorInner::new()),
+        }
+    }
+
+    /// Hand the kernel heap region to the allocator.
+    ///
+    /// # Safety
+    ///
+    /// - The region must be mapped RW and must not be used by anything else.
+    pub unsafe fn init(&self, heap_region: RangeInclusive<usize>) -> Result<(), &'static str> {
+        self.inner.lock(|inner| inner.init(heap_region))
+    }
+
+    /// Print the heap usage statistics.
+    pub fn print_usage(&self) {
+        self.inner.lock(|inner| {
+            let Some(region) = inner.heap_region.clone() else {
+                info!("      Heap not initialized");
+                return;
+            };
+
+            let size = region.end() - region.start() + 1;
+            let (num_free_blocks, largest_free) = inner.free_list_stats();
+
+            let (size_h, size_unit) = common::size_human_readable_ceil(size);
+            let (used_h, used_unit) = common::size_human_readable_ceil(inner.used);
+            let (peak_h, peak_unit) = common::size_human_readable_ceil(inner.peak);
+            let (largest_h, largest_unit) = common::size_human_readable_ceil(largest_free);
+
+            info!(
+                "      {:#010x} - {:#010x} | {: >3} {}",
+                region.start(),
+                region.end(),
+                size_h,
+                size_unit
+            );
+            info!(
+                "      Used: {} {} (peak {} {}), {} allocs, {} frees, {} failed",
+                used_h,
+                used_unit,
+                peak_h,
+                peak_unit,
+                inner.num_allocs,
+                inner.num_frees,
+                inner.num_failures
+            );
+            info!(
+                "      Free blocks: {}, largest: {} {}",
+                num_free_blocks, largest_h, largest_unit
+            );
+        });
+    }
+}
+
+/// Report an allocation failure. Returning a null pointer afterwards hands control to
+/// `alloc::alloc::handle_alloc_error`, which ends in the kernel's panic handler.
+fn alloc_failure(layout: &Layout) {
+    warn!(
+        "Kernel heap: Failed to allocate {} bytes (align {})",
+        layout.size(),
+        layout.align()
+    );
+    KERNEL_HEAP_ALLOCATOR.print_usage();
+}
+
+unsafe impl GlobalAlloc for HeapAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.inner.lock(|inner| inner.alloc(&layout));
+
+        if ptr.is_null() {
+            alloc_failure(&layout);
+        }
+
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.inner.lock(|inner| inner.dealloc(ptr, &layout));
+    }
+}
+
+#[global_allocator]
+static KERNEL_HEAP_ALLOCATOR: HeapAllocator = HeapAllocator::new();
+
+/// Return a reference to the kernel's heap allocator.
+pub fn kernel_heap_allocator() -> &'static HeapAllocator {
+    &KERNEL_HEAP_ALLOCATOR
+}
@@ -0,0 +1,102 @@
+//! Mapping of device MMIO into the kernel's virtual address space.
+
+use crate::{
+    board,
+    memory::mmu::{
+        self, interface::Mmu, AccessPermissions, AttributeFields, KernelGranule, MemAttributes,
+    },
+    synchronization::{interface::Mutex, IrqSafeSpinLock},
+};
+
+/// A device MMIO range mapped into the kernel's virtual address space.
+#[derive(Copy, Clone)]
+pub struct MmioMapping {
+    phys_addr: usize,
+    virt_addr: usize,
+    size: usize,
+}
+
+impl MmioMapping {
+    /// The physical address the mapping was requested for.
+    pub fn phys_addr(&self) -> usize {
+        self.phys_addr
+    }
+
+    /// The virtual address `phys_addr()` is mapped at.
+    pub fn virt_addr(&self) -> usize {
+        self.virt_addr
+    }
+
+    /// The requested size in bytes. The mapping itself covers whole pages.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+}
+
+struct MmioRemapInner {
+    /// The next free address of the board's MMIO remap window, `None` before the first remap.
+    next_virt_addr: Option<usize>,
+}
+
+impl MmioRemapInner {
+    pub const fn new() -> Self {
+        Self {
+            next_virt_addr: None,
+        }
+    }
+
+    /// Hand out `num_pages` pages of the MMIO remap window. They are never given back.
+    fn alloc(&mut self, num_pages: usize) -> Result<usize, &'static str> {
+        let window = board::board().virt_mmio_remap_region();
+
+        let virt_addr = self.next_virt_addr.unwrap_or(*window.start());
+        let end_exclusive = (num_pages << KernelGranule::SHIFT)
+            .checked_add(virt_addr)
+            .ok_or("MMIO remap window exhausted")?;
+
+        if end_exclusive - 1 > *window.end() {
+            return Err("MMIO remap window exhausted");
+        }
+
+        self.next_virt_addr = Some(end_exclusive);
+
+        Ok(virt_addr)
+    }
+}
+
+static MMIO_REMAP: IrqSafeSpinLock<MmioRemapInner> = IrqSafeSpinLock::new(MmioRemapInner::new());
+
+/// Map `size` bytes of device MMIO starting at `phys_addr` as device memory, at a fresh address
+/// of the board's MMIO remap window.
+///
+/// # Safety
+///
+/// - The range must be device MMIO. Mapping DRAM as device memory too would create aliases with
+///   mismatched attributes.
+pub unsafe fn mmio_remap(phys_addr: usize, size: usize) -> Result<MmioMapping, &'static str> {
+    if size == 0 {
+        return Err("Zero sized MMIO mapping requested");
+    }
+
+    let phys_start = phys_addr & !(KernelGranule::SIZE - 1);
+    let phys_end_exclusive = phys_addr
+        .checked_add(size)
+        .and_then(|x| x.checked_next_multiple_of(KernelGranule::SIZE))
+        .ok_or("MMIO range overflows")?;
+    let num_pages = (phys_end_exclusive - phys_start) >> KernelGranule::SHIFT;
+
+    let virt_start = MMIO_REMAP.lock(|inner| inner.alloc(num_pages))?;
+
+    let attribute_fields = AttributeFields {
+        mem_attributes: MemAttributes::Device,
+        acc_perms: AccessPermissions::ReadWrite,
+        execute_never: true,
+    };
+    mmu::mmu().map_pages(virt_start, phys_start, num_pages, &attribute_fields)?;
+
+    Ok(MmioMapping {
+        phys_addr,
+        virt_addr: virt_start + (phys_addr - phys_start),
+        size,
+    })
+}
@@ -0,0 +1,322 @@
+//! Architecture agnostic MMU code.
+
+use core::{fmt, ops::RangeInclusive};
+
+use crate::{
+    board, common,
+    memory::{virt_to_phys, KERNEL_VIRT_OFFSET},
+};
+
+#[path = "../arch/aarch64/memory/mmu.rs"]
+mod arch_mmu;
+
+mod translation_table;
+
+pub use arch_mmu::{mmu, KernelGranule, NUM_ASIDS, USER_ADDR_SPACE_SIZE};
+pub use translation_table::UserTranslationTable;
+
+pub(crate) use arch_mmu::boot;
+
+/// MMU enable errors variants.
+#[derive(Debug)]
+pub enum MmuEnableError {
+    AlreadyEnabled,
+    Other(&'static str),
+}
+
+/// Memory Management interfaces.
+pub mod interface {
+    use crate::memory::mmu::{AttributeFields, MmuEnableError};
+
+    pub trait Mmu {
+        /// Called by the kernel during early init. Supposed to take the translation tables from the
+        /// board-supplied `virt_mem_layout()` and install/activate them for the respective MMU,
+        /// replacing the minimal mapping the boot code runs on.
+        unsafe fn enable_mmu_and_caching(&self) -> Result<(), MmuEnableError>;
+
+        /// Called by each secondary core during its init. Switches the core from the boot
+        /// translation table to the kernel tables `enable_mmu_and_caching()` installed.
+        unsafe fn enable_mmu_and_caching_secondary(&self) -> Result<(), MmuEnableError>;
+
+        /// Returns true if the MMU is enabled, false otherwise.
+        fn is_enabled(&self) -> bool;
+
+        /// Map `num_pages` pages starting at `virt_addr` to the physical pages starting at
+        /// `phys_addr`, replacing any existing mapping.
+        ///
+        /// # Safety
+        ///
+        /// - Changes the mapping of live memory. Nothing may still use the old mapping.
+        unsafe fn map_pages(
+            &self,
+            virt_addr: usize,
+            phys_addr: usize,
+            num_pages: usize,
+            attribute_fields: &AttributeFields,
+        ) -> Result<(), &'static str>;
+
+        /// Remove the mappings of `num_pages` pages starting at `virt_addr`. Later accesses fault.
+        ///
+        /// # Safety
+        ///
+        /// - Nothing may still use the removed mapping.
+        unsafe fn unmap_pages(
+            &self,
+            virt_addr: usize,
+            num_pages: usize,
+        ) -> Result<(), &'static str>;
+
+        /// Change the attributes of `num_pages` mapped pages starting at `virt_addr`.
+        ///
+        /// # Safety
+        ///
+        /// - Nothing may still rely on the old attributes.
+        unsafe fn protect_pages(
+            &self,
+            virt_addr: usize,
+            num_pages: usize,
+            attribute_fields: &AttributeFields,
+        ) -> Result<(), &'static str>;
+
+        /// Install the user translation table at `phys_table_base` in TTBR0, tagged with `asid`,
+        /// and turn on TTBR0 walks.
+        ///
+        /// # Safety
+        ///
+        /// - The table must stay alive until it is deactivated.
+        unsafe fn activate_user_table(&self, phys_table_base: u64, asid: u16);
+
+        /// Remove the user translation table from TTBR0. Afterwards, user addresses fault.
+        ///
+        /// # Safety
+        ///
+        /// - Nothing may still use the user mappings.
+        unsafe fn deactivate_user_table(&self);
+
+        /// Invalidate the TLB entries tagged with `asid` on all cores, before the ASID is reused.
+        fn invalidate_asid(&self, asid: u16);
+    }
+}
+
+/// Describes the characteristics of a translation granule.
+pub struct TranslationGranule<const GRANULE_SIZE: usize>;
+
+/// Architecture agnostic translation types.
+#[derive(Copy, Clone)]
+pub enum Translation {
+    /// The kernel's linear mapping, see `memory::virt_to_phys()`.
+    Linear,
+    Offset(usize),
+    /// No mapping at all, accesses fault. For guard pages.
+    Unmapped,
+}
+
+/// Architecture agnostic memory attributes.
+#[derive(Copy, Clone)]
+pub enum MemAttributes {
+    CacheableDram,
+    Device,
+}
+
+/// Architecture agnostic access permissions. The `User` variants are accessible from user mode as
+/// well, and only valid in user translation tables.
+#[derive(Copy, Clone)]
+pub enum AccessPermissions {
+    ReadOnly,
+    ReadWrite,
+    UserReadOnly,
+    UserReadWrite,
+}
+
+/// Collection of memory attributes.
+#[derive(Copy, Clone)]
+pub struct AttributeFields {
+    pub mem_attributes: MemAttributes,
+    pub acc_perms: AccessPermissions,
+    pub execute_never: bool,
+}
+
+/// Architecture agnostic descriptor for a memory range.
+pub struct TranslationDescriptor {
+    pub name: &'static str,
+    pub virtual_range: fn() -> RangeInclusive<usize>,
+    pub physical_range_translation: Translation,
+    pub attribute_fields: AttributeFields,
+}
+
+/// Type for expressing the kernel's virtual memory layout.
+#[derive(Copy, Clone)]
+pub struct KernelVirtualLayout {
+    /// The last (inclusive) address of the address space. It starts at `KERNEL_VIRT_OFFSET`.
+    max_virt_addr_inclusive: usize,
+
+    /// Descriptors for non-standard (normal cacheable DRAM) memory regions.
+    inner: &'static [TranslationDescriptor],
+}
+
+impl fmt::Display for MmuEnableError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MmuEnableError::AlreadyEnabled => write!(f, "MMU is already enabled"),
+            MmuEnableError::Other(x) => write!(f, "{}", x),
+        }
+    }
+}
+
+impl<const GRANULE_SIZE: usize> TranslationGranule<GRANULE_SIZE> {
+    /// The granule's size.
+    pub const SIZE: usize = Self::size_checked();
+
+    /// The granule's shift, aka log2(size).
+    pub const SHIFT: usize = Self::SIZE.trailing_zeros() as usize;
+
+    const fn size_checked() -> usize {
+        assert!(GRANULE_SIZE.is_power_of_two());
+
+        GRANULE_SIZE
+    }
+}
+
+impl AccessPermissions {
+    /// Returns true if user mode may access the memory.
+    pub const fn is_user(&self) -> bool {
+        matches!(
+            self,
+            AccessPermissions::UserReadOnly | AccessPermissions::UserReadWrite
+        )
+    }
+}
+
+impl Default for AttributeFields {
+    fn default() -> Self {
+        Self {
+            mem_attributes: MemAttributes::CacheableDram,
+            acc_perms: AccessPermissions::ReadWrite,
+            execute_never: true,
+        }
+    }
+}
+
+/// Display of a TranslationDescriptor.
+impl fmt::Display for TranslationDescriptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let start = *(self.virtual_range)().start();
+        let end = *(self.virtual_range)().end();
+        let size = end - start + 1;
+
+        let (size, unit) = common::size_human_readable_ceil(size);
+
+        let attr = match self.attribute_fields.mem_attributes {
+            MemAttributes::CacheableDram => "C",
+            MemAttributes::Device => "Dev",
+        };
+
+        let acc_p = match self.attribute_fields.acc_perms {
+            AccessPermissions::ReadOnly => "RO",
+            AccessPermissions::ReadWrite => "RW",
+            AccessPermissions::UserReadOnly => "URO",
+            AccessPermissions::UserReadWrite => "URW",
+        };
+
+        let xn = match self.attribute_fields.execute_never {
+            true => "PXN",
+            false => "PX",
+        };
+
+        if let Translation::Unmapped = self.physical_range_translation {
+            return write!(
+                f,
+                "      {:#018x} - {:#018x} | {: >3} {} | {: <10} | {}",
+                start, end, size, unit, "Unmapped", self.name
+            );
+        }
+
+        write!(
+            f,
+            "      {:#018x} - {:#018x} | {: >3} {} | {: <3} {} {: <3} | {}",
+            start, end, size, unit, attr, acc_p, xn, self.name
+        )
+    }
+}
+
+impl KernelVirtualLayout {
+    /// Create a new instance.
+    pub const fn new(max: usize, layout: &'static [TranslationDescriptor]) -> Self {
+        Self {
+            max_virt_addr_inclusive: max,
+            inner: layout,
+        }
+    }
+
+    /// The size of the address space.
+    pub const fn addr_space_size(&self) -> usize {
+        (self.max_virt_addr_inclusive - KERNEL_VIRT_OFFSET) + 1
+    }
+
+    /// For a virtual address, find and return the physical output address and corresponding
+    /// attributes, or `None` if the address must stay unmapped.
+    pub fn virt_addr_properties(
+        &self,
+        virt_addr: usize,
+    ) -> Result<Option<(usize, AttributeFields)>, &'static str> {
+        if !(KERNEL_VIRT_OFFSET..=self.max_virt_addr_inclusive).contains(&virt_addr) {
+            return Err("Address out of range");
+        }
+
+        if let Some(i) = self.find_descriptor(virt_addr) {
+            let output_addr = match i.physical_range_translation {
+                Translation::Linear => virt_to_phys(virt_addr),
+                Translation::Offset(a) => a + (virt_addr - ((i.virtual_range)().start())),
+                Translation::Unmapped => return Ok(None),
+            };
+
+            return Ok(Some((output_addr, i.attribute_fields)));
+        }
+
+        Ok(Some((virt_to_phys(virt_addr), AttributeFields::default())))
+    }
+
+    /// Return the descriptor covering a virtual address, if any.
+    pub fn find_descriptor(&self, virt_addr: usize) -> Option<&'static TranslationDescriptor> {
+        self.inner
+            .iter()
+            .find(|i| (i.virtual_range)().contains(&virt_addr))
+    }
+
+    /// Returns true if no descriptor covers any part of a virtual range, so all of it gets the
+    /// default linear mapping.
+    pub fn is_default_mapped(&self, virt_range: RangeInclusive<usize>) -> bool {
+        self.inner.iter().all(|i| {
+            let range = (i.virtual_range)();
+
+            range.end() < virt_range.start() || range.start() > virt_range.end()
+        })
+    }
+
+    /// Returns true if a single unmapped descriptor covers all of a virtual range.
+    pub fn is_unmapped(&self, virt_range: RangeInclusive<usize>) -> bool {
+        match self.find_descriptor(*virt_range.start()) {
+            Some(i) => {
+                matches!(i.physical_range_translation, Translation::Unmapped)
+                    && (i.virtual_range)().contains(virt_range.end())
+            }
+            None => false,
+        }
+    }
+
+    /// Print the memory layout.
+    pub fn print_layout(&self) {
+        use crate::info;
+
+        for i in self.inner.iter() {
+            info!("{}", i);
+        }
+    }
+}
+
+/// Return the current board's virtual memory layout.
+pub fn virt_mem_layout() -> KernelVirtualLayout {
+    let brd = board::board();
+
+    KernelVirtualLayout::new(brd.virt_addr_space_end_inclusive(), brd.virt_mem_layout())
+}
@@ -0,0 +1,8 @@
+//! Memory accesses that may fault without taking down the kernel.
+
+#[path = "../arch/aarch64/memory/nofault.rs"]
+mod arch_nofault;
+
+pub use arch_nofault::copy_from_nofault;
+
+pub(crate) use arch_nofault::register_fixups;
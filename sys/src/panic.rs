@@ -1,15 +1,34 @@
-use core::panic::PanicInfo;
+use core::{panic::PanicInfo, time::Duration};
 
-use crate::{cpu, println};
+use crate::{
+    board, cpu, force_println,
+    synchronization::{interface::Mutex, IrqSafeNullLock},
+    timer_manager,
+};
 
-/// Stop immediately if called a second time.
+/// How long a panic waits before rebooting the board. `None` parks the core instead.
+static REBOOT_DELAY: IrqSafeNullLock<Option<Duration>> = IrqSafeNullLock::new(None);
+
+/// Let panics reboot the board after `delay`, or park the core if `None`, the default. Only call
+/// once the board can reboot.
+pub fn set_reboot_delay(delay: Option<Duration>) {
+    REBOOT_DELAY.lock(|x| *x = delay);
+}
+
+/// Stop immediately if called a second time on the same core. Panics on other cores still get
+/// reported.
 fn panic_prevent_reenter() {
     use core::sync::atomic::{AtomicBool, Ordering};
 
-    static PANIC_IN_PROGRESS: AtomicBool = AtomicBool::new(false);
+    // Indexed by the core ID instead of held in a `PerCpu`, as the panic may come before
+    // `percpu::init_local()`.
+    static PANIC_IN_PROGRESS: [AtomicBool; cpu::smp::NUM_CORES] =
+        [const { AtomicBool::new(false) }; cpu::smp::NUM_CORES];
+
+    let in_progress = &PANIC_IN_PROGRESS[cpu::smp::core_id::<usize>()];
 
-    if !PANIC_IN_PROGRESS.load(Ordering::Relaxed) {
-        PANIC_IN_PROGRESS.store(true, Ordering::Relaxed);
+    if !in_progress.load(Ordering::Relaxed) {
+        in_progress.store(true, Ordering::Relaxed);
 
         return;
     }
@@ -26,15 +45,26 @@ fn panic(info: &PanicInfo) -> ! {
         _ => ("???", 0, 0),
     };
 
-    println!(
-        "Kernel panic!\n\
+    // The panic may have hit while the console's locks were held, e.g. by a fault in the middle of
+    // printing, so the message is forced past them.
+    force_println!(
+        "Kernel panic on core {}!\n\
         Panic location:\n      File '{}', line {}, column {}\n\
         {}",
+        cpu::smp::core_id::<usize>(),
         location,
         line,
         column,
         info.message(),
     );
 
+    if let Some(delay) = REBOOT_DELAY.lock(|x| *x) {
+        force_println!("Rebooting in {} ms", delay.as_millis());
+
+        // IRQs may be broken by now, so don't rely on them.
+        timer_manager::timer_manager().spin_for(delay);
+        board::board().reboot()
+    }
+
     cpu::wait_forever()
 }
@@ -7,6 +7,12 @@ pub fn _print(args: fmt::Arguments) {
     console::console().write_fmt(args).unwrap();
 }
 
+#[doc(hidden)]
+pub fn _force_print(args: fmt::Arguments) {
+    // Panicking about a failed write would only make things worse on these paths.
+    let _ = console::console().force_write_fmt(args);
+}
+
 /// Prints without a newline.
 #[macro_export]
 macro_rules! print {
@@ -24,3 +30,14 @@ macro_rules! println {
     };
     ($fmt:expr, $($arg:tt)*) => ($crate::print!(concat!($fmt, "\n"), $($arg)*));
 }
+
+/// Prints with a newline, even if the console's locks are held, see
+/// `console::interface::Write::force_write_fmt()`. For panics and other reports that may be raised
+/// while printing.
+#[macro_export]
+macro_rules! force_println {
+    ($fmt:expr) => {
+        $crate::print::_force_print(format_args!(concat!($fmt, "\n")))
+    };
+    ($fmt:expr, $($arg:tt)*) => ($crate::print::_force_print(format_args!(concat!($fmt, "\n"), $($arg)*)));
+}
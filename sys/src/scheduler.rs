@@ -0,0 +1,436 @@
+//! Preemptive scheduling of kernel threads.
+//!
+//! A thread's registers are kept in an exception context on its own stack. Every exception return
+//! asks the scheduler which context to restore, so switching threads is just returning into
+//! another one. The timer tick, `yield_now()`, `sleep()` and `exit()` request such a switch.
+
+use alloc::{boxed::Box, collections::VecDeque, vec::Vec};
+use core::time::Duration;
+
+use crate::{
+    cpu, exception, info,
+    memory::{
+        frame_alloc::{frame_allocator, FRAME_SIZE},
+        phys_to_virt, KERNEL_STACK_SIZE,
+    },
+    synchronization::{interface::Mutex, IrqSafeNullLock},
+    task::UserContext,
+    timer_manager::timer_manager,
+};
+
+/// Identifies a thread.
+pub type ThreadId = usize;
+
+/// How long a thread runs before others get their turn.
+pub const TIME_SLICE: Duration = Duration::from_millis(10);
+
+/// The priority of threads started by `spawn()` unless told otherwise.
+pub const DEFAULT_PRIORITY: u8 = 128;
+
+/// How the next thread is picked from the ready ones.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Policy {
+    /// Every ready thread gets a time slice in turn.
+    RoundRobin,
+
+    /// The ready thread with the highest priority runs. Threads of the same priority take turns.
+    Priority,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum ThreadState {
+    Ready,
+    Running,
+
+    /// Sleeping until the uptime reaches the value.
+    Sleeping(Duration),
+
+    /// Waiting to be reaped.
+    Exited,
+}
+
+/// A kernel stack, placed as `KERNEL_STACK_SIZE` demands.
+///
+/// The lower half of the block stays allocated but unused, so an overflow that raises no exception
+/// runs into memory nobody else owns instead of into heap, page tables or user pages.
+pub(crate) struct KernelStack {
+    /// The start of the whole block.
+    phys_addr: usize,
+}
+
+impl KernelStack {
+    const NUM_FRAMES: usize = 2 * KERNEL_STACK_SIZE / FRAME_SIZE;
+
+    pub(crate) fn new() -> Result<Self, &'static str> {
+        let phys_addr =
+            frame_allocator().alloc_frames_aligned(Self::NUM_FRAMES, Self::NUM_FRAMES)?;
+
+        Ok(Self { phys_addr })
+    }
+
+    /// The initial stack pointer.
+    pub(crate) fn top(&self) -> usize {
+        phys_to_virt(self.phys_top())
+    }
+
+    /// The initial stack pointer, for code running before the MMU is on.
+    pub(crate) fn phys_top(&self) -> usize {
+        self.phys_addr + 2 * KERNEL_STACK_SIZE
+    }
+}
+
+impl Drop for KernelStack {
+    fn drop(&mut self) {
+        if let Err(x) = frame_allocator().free_frames(self.phys_addr, Self::NUM_FRAMES) {
+            panic!("Freeing kernel stack: {}", x);
+        }
+    }
+}
+
+struct Thread {
+    id: ThreadId,
+    name: &'static str,
+    priority: u8,
+    state: ThreadState,
+
+    /// The address of the saved exception context. Only valid while the thread is not running.
+    context: usize,
+
+    /// Freed together with the thread. `None` for the boot thread, which runs on the boot stack.
+    _stack: Option<KernelStack>,
+
+    user_context: UserContext,
+}
+
+/// The closure a spawned thread runs.
+type ThreadFn = Box<dyn FnOnce() + Send>;
+
+extern "C" fn thread_entry(arg: usize) -> ! {
+    let f = unsafe { Box::from_raw(arg as *mut ThreadFn) };
+    f();
+
+    scheduler().exit()
+}
+
+extern "C" fn idle_entry(_arg: usize) -> ! {
+    cpu::wait_forever()
+}
+
+struct SchedulerInner {
+    threads: Vec<Thread>,
+    ready: VecDeque<ThreadId>,
+    current: ThreadId,
+    idle: ThreadId,
+    next_id: ThreadId,
+    policy: Policy,
+    need_resched: bool,
+    started: bool,
+}
+
+impl SchedulerInner {
+    pub const fn new() -> Self {
+        Self {
+            threads: Vec::new(),
+            ready: VecDeque::new(),
+            current: 0,
+            idle: 0,
+            next_id: 0,
+            policy: Policy::RoundRobin,
+            need_resched: false,
+            started: false,
+        }
+    }
+
+    fn thread_mut(&mut self, id: ThreadId) -> &mut Thread {
+        self.threads
+            .iter_mut()
+            .find(|thread| thread.id == id)
+            .expect("Unknown thread")
+    }
+
+    fn priority(&self, id: ThreadId) -> u8 {
+        self.threads
+            .iter()
+            .find(|thread| thread.id == id)
+            .map_or(0, |thread| thread.priority)
+    }
+
+    /// Create a thread that calls `entry(arg)` on a stack of its own. It is not queued.
+    fn create_thread(
+        &mut self,
+        name: &'static str,
+        priority: u8,
+        entry: extern "C" fn(usize) -> !,
+        arg: usize,
+    ) -> Result<ThreadId, &'static str> {
+        let stack = KernelStack::new()?;
+        let context = unsafe { exception::prepare_thread_context(stack.top(), entry, arg) };
+
+        let id = self.next_id;
+        self.next_id += 1;
+
+        self.threads.push(Thread {
+            id,
+            name,
+            priority,
+            state: ThreadState::Ready,
+            context,
+            _stack: Some(stack),
+            user_context: UserContext::new(),
+        });
+
+        Ok(id)
+    }
+
+    /// Remove the next thread to run from the ready queue. `None` means the current thread goes
+    /// on running.
+    fn pick_next(&mut self) -> Option<ThreadId> {
+        let current_runnable = self.thread_mut(self.current).state == ThreadState::Running;
+
+        let index = match self.policy {
+            Policy::RoundRobin => (!self.ready.is_empty()).then_some(0),
+            Policy::Priority => {
+                let mut best: Option<usize> = None;
+                for (i, id) in self.ready.iter().enumerate() {
+                    if best.is_none_or(|x| self.priority(*id) > self.priority(self.ready[x])) {
+                        best = Some(i);
+                    }
+                }
+
+                // Lower priority threads don't get to preempt the current one.
+                best.filter(|x| {
+                    !current_runnable
+                        || self.current == self.idle
+                        || self.priority(self.ready[*x]) >= self.priority(self.current)
+                })
+            }
+        };
+
+        match index {
+            Some(x) => self.ready.remove(x),
+            None if current_runnable => None,
+            None => Some(self.idle),
+        }
+    }
+
+    /// Make sleepers whose time has come ready again.
+    fn wake_sleepers(&mut self) {
+        let now = timer_manager().uptime();
+
+        for thread in self.threads.iter_mut() {
+            if let ThreadState::Sleeping(until) = thread.state {
+                if until > now {
+                    continue;
+                }
+
+                // The current thread may not have switched away yet.
+                if thread.id == self.current {
+                    thread.state = ThreadState::Running;
+                } else {
+                    thread.state = ThreadState::Ready;
+                    self.ready.push_back(thread.id);
+                }
+            }
+        }
+    }
+
+    fn switch_context(&mut self, context: usize) -> usize {
+        if !self.started || !self.need_resched {
+            return context;
+        }
+        self.need_resched = false;
+
+        let Some(next) = self.pick_next() else {
+            return context;
+        };
+
+        let prev = self.current;
+        let idle = self.idle;
+
+        let prev_thread = self.thread_mut(prev);
+        prev_thread.context = context;
+        prev_thread.user_context = UserContext::save();
+
+        if prev_thread.state == ThreadState::Running {
+            prev_thread.state = ThreadState::Ready;
+
+            if prev != idle {
+                self.ready.push_back(prev);
+            }
+        }
+
+        // Still running on the stack of `prev`, so it is reaped on a later switch.
+        self.threads
+            .retain(|thread| thread.state != ThreadState::Exited || thread.id == prev);
+
+        self.current = next;
+        let next_thread = self.thread_mut(next);
+        next_thread.state = ThreadState::Running;
+        unsafe { next_thread.user_context.restore() };
+
+        next_thread.context
+    }
+}
+
+/// The scheduler of kernel threads.
+pub struct Scheduler {
+    inner: IrqSafeNullLock<SchedulerInner>,
+}
+
+impl Scheduler {
+    /// Create an instance.
+    pub const fn new() -> Self {
+        Self {
+            inner: IrqSafeNullLock::new(SchedulerInner::new()),
+        }
+    }
+
+    /// Turn the running code into the thread "main" and start scheduling.
+    pub fn init(&self) -> Result<(), &'static str> {
+        self.inner.lock(|inner| {
+            if inner.started {
+                return Err("Scheduler already started");
+            }
+
+            let main = inner.next_id;
+            inner.next_id += 1;
+            inner.threads.push(Thread {
+                id: main,
+                name: "main",
+                priority: DEFAULT_PRIORITY,
+                state: ThreadState::Running,
+                context: 0,
+                _stack: None,
+                user_context: UserContext::new(),
+            });
+
+            inner.idle = inner.create_thread("idle", 0, idle_entry, 0)?;
+            inner.current = main;
+            inner.started = true;
+
+            Ok(())
+        })?;
+
+        timer_manager()
+            .set_timeout_periodic(TIME_SLICE, || scheduler().tick())
+            .map(|_| ())
+    }
+
+    /// Set the policy picking the next thread.
+    pub fn set_policy(&self, policy: Policy) {
+        self.inner.lock(|inner| inner.policy = policy);
+    }
+
+    /// Start a thread running `f`. It exits when `f` returns.
+    pub fn spawn(
+        &self,
+        name: &'static str,
+        priority: u8,
+        f: impl FnOnce() + Send + 'static,
+    ) -> Result<ThreadId, &'static str> {
+        let f: Box<ThreadFn> = Box::new(Box::new(f));
+        let arg = Box::into_raw(f) as usize;
+
+        self.inner.lock(|inner| {
+            match inner.create_thread(name, priority, thread_entry, arg) {
+                Ok(id) => {
+                    inner.ready.push_back(id);
+
+                    // A higher priority thread shouldn't wait for the next tick.
+                    inner.need_resched = true;
+
+                    Ok(id)
+                }
+                Err(x) => {
+                    drop(unsafe { Box::from_raw(arg as *mut ThreadFn) });
+
+                    Err(x)
+                }
+            }
+        })
+    }
+
+    /// Let other ready threads run.
+    pub fn yield_now(&self) {
+        self.inner.lock(|inner| inner.need_resched = true);
+        exception::yield_to_scheduler();
+    }
+
+    /// Let other threads run for at least `duration`. Until the scheduler is started, the core
+    /// waits in `TimerManager::sleep_for()` instead.
+    pub fn sleep(&self, duration: Duration) {
+        let started = self.inner.lock(|inner| {
+            if inner.started {
+                let until = timer_manager().uptime() + duration;
+                let current = inner.current;
+
+                inner.thread_mut(current).state = ThreadState::Sleeping(until);
+                inner.need_resched = true;
+            }
+
+            inner.started
+        });
+
+        match started {
+            true => exception::yield_to_scheduler(),
+            false => timer_manager().sleep_for(duration),
+        }
+    }
+
+    /// End the current thread.
+    pub fn exit(&self) -> ! {
+        self.inner.lock(|inner| {
+            let current = inner.current;
+
+            inner.thread_mut(current).state = ThreadState::Exited;
+            inner.need_resched = true;
+        });
+        exception::yield_to_scheduler();
+
+        panic!("Exited thread still running. Are IRQs masked?")
+    }
+
+    /// Return the ID of the current thread.
+    pub fn current_thread_id(&self) -> ThreadId {
+        self.inner.lock(|inner| inner.current)
+    }
+
+    /// Called every `TIME_SLICE`: wake up sleepers and end the current time slice.
+    pub fn tick(&self) {
+        self.inner.lock(|inner| {
+            if !inner.started {
+                return;
+            }
+
+            inner.wake_sleepers();
+            inner.need_resched = true;
+        });
+    }
+
+    /// Called on the exception return path with the address of the context about to be restored.
+    /// Returns the address of the context to restore instead.
+    pub(crate) fn switch_context(&self, context: usize) -> usize {
+        self.inner.lock(|inner| inner.switch_context(context))
+    }
+
+    /// Print the threads.
+    pub fn print_threads(&self) {
+        self.inner.lock(|inner| {
+            info!("      {:?} scheduling", inner.policy);
+
+            for thread in inner.threads.iter() {
+                info!(
+                    "        {: >3}. {: <12} prio {: >3} {:?}",
+                    thread.id, thread.name, thread.priority, thread.state
+                );
+            }
+        });
+    }
+}
+
+static SCHEDULER: Scheduler = Scheduler::new();
+
+/// Return a reference to the kernel's thread scheduler.
+pub fn scheduler() -> &'static Scheduler {
+    &SCHEDULER
+}
@@ -1,9 +1,37 @@
 //! Synchronization primitives.
 
-use core::cell::UnsafeCell;
+use core::{
+    cell::UnsafeCell,
+    hint,
+    marker::PhantomData,
+    ops,
+    panic::Location,
+    sync::atomic::{AtomicU32, Ordering},
+};
 
 use crate::{exception, state};
 
+#[cfg(feature = "lockdep")]
+pub mod lockdep;
+
+/// Without the `lockdep` feature, lock order is not validated.
+#[cfg(not(feature = "lockdep"))]
+mod lockdep {
+    use core::panic::Location;
+
+    #[inline(always)]
+    pub(super) fn acquire(_addr: usize, _location: &'static Location<'static>) {}
+
+    #[inline(always)]
+    pub(super) fn acquire_read(_addr: usize, _location: &'static Location<'static>) {}
+
+    #[inline(always)]
+    pub(super) fn release(_addr: usize) {}
+
+    #[inline(always)]
+    pub(super) fn forget(_addr: usize) {}
+}
+
 /// Synchronization interfaces.
 pub mod interface {
     /// Any object implementing this trait guarantees exclusive access to the data wrapped within
@@ -29,7 +57,8 @@ pub mod interface {
     }
 }
 
-/// A pseudo-lock for teaching purposes.
+/// A pseudo-lock for teaching purposes. It only masks IRQs on the executing core, which is not
+/// enough once the secondary cores run. Use `IrqSafeSpinLock` for data they share.
 pub struct IrqSafeNullLock<T>
 where
     T: ?Sized,
@@ -49,14 +78,520 @@ impl<T> IrqSafeNullLock<T> {
     }
 }
 
+impl<T> IrqSafeNullLock<T>
+where
+    T: ?Sized,
+{
+    fn addr(&self) -> usize {
+        self as *const Self as *const () as usize
+    }
+}
+
+impl<T> Drop for IrqSafeNullLock<T>
+where
+    T: ?Sized,
+{
+    fn drop(&mut self) {
+        lockdep::forget(self.addr());
+    }
+}
+
 impl<T> interface::Mutex for IrqSafeNullLock<T> {
     type Data = T;
 
+    #[cfg_attr(feature = "lockdep", track_caller)]
     fn lock<'a, R>(&'a self, f: impl FnOnce(&'a mut Self::Data) -> R) -> R {
         let data = unsafe { &mut *self.data.get() };
 
+        lockdep::acquire(self.addr(), Location::caller());
+
         // Execute the closure while IRQs are masked.
-        exception::asynchronous::exec_with_irq_masked(|| f(data))
+        let ret = exception::asynchronous::exec_with_irq_masked(|| f(data));
+        lockdep::release(self.addr());
+
+        ret
+    }
+}
+
+/// A ticket lock. Cores get the lock in the order they asked for it, so none of them starves.
+///
+/// The atomics compile to exclusive loads and stores (LDAXR/STLXR), or to LSE atomics on targets
+/// that have them.
+struct TicketLock {
+    next_ticket: AtomicU32,
+    now_serving: AtomicU32,
+}
+
+impl TicketLock {
+    const fn new() -> Self {
+        Self {
+            next_ticket: AtomicU32::new(0),
+            now_serving: AtomicU32::new(0),
+        }
+    }
+
+    fn acquire(&self) {
+        let ticket = self.next_ticket.fetch_add(1, Ordering::Relaxed);
+
+        while self.now_serving.load(Ordering::Acquire) != ticket {
+            hint::spin_loop();
+        }
+    }
+
+    /// Take the lock if it is free, without waiting.
+    fn try_acquire(&self) -> bool {
+        let now_serving = self.now_serving.load(Ordering::Acquire);
+
+        self.next_ticket
+            .compare_exchange(
+                now_serving,
+                now_serving.wrapping_add(1),
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            )
+            .is_ok()
+    }
+
+    /// The address the lock is validated by, see `lockdep`.
+    fn addr(&self) -> usize {
+        self as *const Self as usize
+    }
+
+    fn release(&self) {
+        // Only the holder writes `now_serving`.
+        let next = self.now_serving.load(Ordering::Relaxed).wrapping_add(1);
+
+        self.now_serving.store(next, Ordering::Release);
+    }
+}
+
+/// Exclusive access to the data of a `SpinLock` or an `IrqSafeSpinLock`. The lock is released
+/// when the guard is dropped.
+///
+/// A guard of an `IrqSafeSpinLock` also restores the IRQ mask (DAIF) that was saved when taking
+/// it. Such guards must therefore be dropped in the reverse order they were taken in, and on the
+/// core that took them, which is why they can't be sent to other threads.
+pub struct MutexGuard<'a, T>
+where
+    T: ?Sized,
+{
+    lock: &'a TicketLock,
+    data: *mut T,
+    saved_daif: Option<u64>,
+}
+
+impl<T> ops::Deref for MutexGuard<'_, T>
+where
+    T: ?Sized,
+{
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.data }
+    }
+}
+
+impl<T> ops::DerefMut for MutexGuard<'_, T>
+where
+    T: ?Sized,
+{
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.data }
+    }
+}
+
+impl<T> Drop for MutexGuard<'_, T>
+where
+    T: ?Sized,
+{
+    fn drop(&mut self) {
+        self.lock.release();
+        lockdep::release(self.lock.addr());
+
+        if let Some(saved_daif) = self.saved_daif {
+            exception::asynchronous::local_irq_restore(saved_daif);
+        }
+    }
+}
+
+/// A spinlock. It leaves IRQs alone, so data an IRQ handler touches needs an `IrqSafeSpinLock`
+/// instead: the handler would spin forever on a lock its own core holds.
+pub struct SpinLock<T>
+where
+    T: ?Sized,
+{
+    lock: TicketLock,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T> Send for SpinLock<T> where T: ?Sized + Send {}
+unsafe impl<T> Sync for SpinLock<T> where T: ?Sized + Send {}
+
+impl<T> SpinLock<T> {
+    /// Create an instance.
+    pub const fn new(data: T) -> Self {
+        Self {
+            lock: TicketLock::new(),
+            data: UnsafeCell::new(data),
+        }
+    }
+}
+
+impl<T> SpinLock<T>
+where
+    T: ?Sized,
+{
+    /// Take the lock until the returned guard is dropped.
+    #[cfg_attr(feature = "lockdep", track_caller)]
+    pub fn lock_guard(&self) -> MutexGuard<'_, T> {
+        lockdep::acquire(self.lock.addr(), Location::caller());
+        self.lock.acquire();
+
+        MutexGuard {
+            lock: &self.lock,
+            data: self.data.get(),
+            saved_daif: None,
+        }
+    }
+}
+
+impl<T> Drop for SpinLock<T>
+where
+    T: ?Sized,
+{
+    fn drop(&mut self) {
+        lockdep::forget(self.lock.addr());
+    }
+}
+
+impl<T> interface::Mutex for SpinLock<T> {
+    type Data = T;
+
+    #[cfg_attr(feature = "lockdep", track_caller)]
+    fn lock<'a, R>(&'a self, f: impl FnOnce(&'a mut Self::Data) -> R) -> R {
+        let _guard = self.lock_guard();
+
+        f(unsafe { &mut *self.data.get() })
+    }
+}
+
+/// A spinlock that masks IRQs on the executing core while it is held, and restores the previous
+/// mask afterwards.
+pub struct IrqSafeSpinLock<T>
+where
+    T: ?Sized,
+{
+    lock: TicketLock,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T> Send for IrqSafeSpinLock<T> where T: ?Sized + Send {}
+unsafe impl<T> Sync for IrqSafeSpinLock<T> where T: ?Sized + Send {}
+
+impl<T> IrqSafeSpinLock<T> {
+    /// Create an instance.
+    pub const fn new(data: T) -> Self {
+        Self {
+            lock: TicketLock::new(),
+            data: UnsafeCell::new(data),
+        }
+    }
+}
+
+impl<T> IrqSafeSpinLock<T>
+where
+    T: ?Sized,
+{
+    /// Mask IRQs and take the lock until the returned guard is dropped.
+    #[cfg_attr(feature = "lockdep", track_caller)]
+    pub fn lock_guard(&self) -> MutexGuard<'_, T> {
+        let saved_daif = exception::asynchronous::local_irq_mask_save();
+        lockdep::acquire(self.lock.addr(), Location::caller());
+        self.lock.acquire();
+
+        MutexGuard {
+            lock: &self.lock,
+            data: self.data.get(),
+            saved_daif: Some(saved_daif),
+        }
+    }
+
+    /// Like `lock_guard()`, but return `None` instead of waiting if the lock is held. As it can't
+    /// deadlock, the lock order is not validated.
+    pub fn try_lock_guard(&self) -> Option<MutexGuard<'_, T>> {
+        let saved_daif = exception::asynchronous::local_irq_mask_save();
+
+        if !self.lock.try_acquire() {
+            exception::asynchronous::local_irq_restore(saved_daif);
+
+            return None;
+        }
+
+        Some(MutexGuard {
+            lock: &self.lock,
+            data: self.data.get(),
+            saved_daif: Some(saved_daif),
+        })
+    }
+
+    /// Return a pointer to the data, regardless of the lock.
+    ///
+    /// # Safety
+    ///
+    /// - Only for last resorts like printing a panic, when the holder may have been interrupted
+    ///   on the executing core and will never release the lock. Accesses race with the holder's.
+    pub unsafe fn data_ptr(&self) -> *mut T {
+        self.data.get()
+    }
+}
+
+impl<T> Drop for IrqSafeSpinLock<T>
+where
+    T: ?Sized,
+{
+    fn drop(&mut self) {
+        lockdep::forget(self.lock.addr());
+    }
+}
+
+impl<T> interface::Mutex for IrqSafeSpinLock<T> {
+    type Data = T;
+
+    #[cfg_attr(feature = "lockdep", track_caller)]
+    fn lock<'a, R>(&'a self, f: impl FnOnce(&'a mut Self::Data) -> R) -> R {
+        let _guard = self.lock_guard();
+
+        f(unsafe { &mut *self.data.get() })
+    }
+}
+
+/// Shared access to the data of an `IrqSafeRwSpinLock`. The read lock is released and the IRQ mask
+/// restored when the guard is dropped, see `MutexGuard`.
+pub struct ReadGuard<'a, T>
+where
+    T: ?Sized,
+{
+    lock: &'a IrqSafeRwSpinLock<T>,
+    saved_daif: u64,
+
+    /// Guards restore the IRQ mask of their core.
+    _not_send: PhantomData<*const ()>,
+}
+
+impl<T> ops::Deref for ReadGuard<'_, T>
+where
+    T: ?Sized,
+{
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T> Drop for ReadGuard<'_, T>
+where
+    T: ?Sized,
+{
+    fn drop(&mut self) {
+        self.lock.release_read();
+        lockdep::release(self.lock.addr());
+        exception::asynchronous::local_irq_restore(self.saved_daif);
+    }
+}
+
+/// Exclusive access to the data of an `IrqSafeRwSpinLock`. The write lock is released and the IRQ
+/// mask restored when the guard is dropped, see `MutexGuard`.
+pub struct WriteGuard<'a, T>
+where
+    T: ?Sized,
+{
+    lock: &'a IrqSafeRwSpinLock<T>,
+    saved_daif: u64,
+
+    /// Guards restore the IRQ mask of their core.
+    _not_send: PhantomData<*const ()>,
+}
+
+impl<T> ops::Deref for WriteGuard<'_, T>
+where
+    T: ?Sized,
+{
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T> ops::DerefMut for WriteGuard<'_, T>
+where
+    T: ?Sized,
+{
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T> Drop for WriteGuard<'_, T>
+where
+    T: ?Sized,
+{
+    fn drop(&mut self) {
+        self.lock.release_write();
+        lockdep::release(self.lock.addr());
+        exception::asynchronous::local_irq_restore(self.saved_daif);
+    }
+}
+
+/// A reader-writer spinlock. Any number of readers or a single writer hold it at a time. IRQs are
+/// masked on the executing core while it is held, like with `IrqSafeSpinLock`.
+///
+/// A waiting writer keeps new readers out, so a steady stream of readers can't starve it. This
+/// also means reads must not nest: the inner one would wait for the writer, which waits for the
+/// outer one.
+pub struct IrqSafeRwSpinLock<T>
+where
+    T: ?Sized,
+{
+    /// The number of readers, plus the `WRITER` and `WRITER_WAITING` bits.
+    state: AtomicU32,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T> Send for IrqSafeRwSpinLock<T> where T: ?Sized + Send {}
+unsafe impl<T> Sync for IrqSafeRwSpinLock<T> where T: ?Sized + Send + Sync {}
+
+impl<T> IrqSafeRwSpinLock<T> {
+    /// Create an instance.
+    pub const fn new(data: T) -> Self {
+        Self {
+            state: AtomicU32::new(0),
+            data: UnsafeCell::new(data),
+        }
+    }
+}
+
+impl<T> IrqSafeRwSpinLock<T>
+where
+    T: ?Sized,
+{
+    const WRITER: u32 = 1 << 31;
+    const WRITER_WAITING: u32 = 1 << 30;
+
+    /// Mask IRQs and take the lock for writing until the returned guard is dropped.
+    #[cfg_attr(feature = "lockdep", track_caller)]
+    pub fn write_guard(&self) -> WriteGuard<'_, T> {
+        let saved_daif = exception::asynchronous::local_irq_mask_save();
+        lockdep::acquire(self.addr(), Location::caller());
+        self.acquire_write();
+
+        WriteGuard {
+            lock: self,
+            saved_daif,
+            _not_send: PhantomData,
+        }
+    }
+
+    /// Mask IRQs and take the lock for reading until the returned guard is dropped.
+    #[cfg_attr(feature = "lockdep", track_caller)]
+    pub fn read_guard(&self) -> ReadGuard<'_, T> {
+        let saved_daif = exception::asynchronous::local_irq_mask_save();
+        lockdep::acquire(self.addr(), Location::caller());
+        self.acquire_read();
+
+        ReadGuard {
+            lock: self,
+            saved_daif,
+            _not_send: PhantomData,
+        }
+    }
+
+    /// The address the lock is validated by, see `lockdep`.
+    fn addr(&self) -> usize {
+        self as *const Self as *const () as usize
+    }
+
+    fn acquire_write(&self) {
+        loop {
+            let state = self.state.load(Ordering::Relaxed);
+
+            if state & !Self::WRITER_WAITING == 0 {
+                if self
+                    .state
+                    .compare_exchange_weak(
+                        state,
+                        Self::WRITER,
+                        Ordering::Acquire,
+                        Ordering::Relaxed,
+                    )
+                    .is_ok()
+                {
+                    return;
+                }
+
+                continue;
+            }
+
+            if state & Self::WRITER_WAITING == 0 {
+                self.state.fetch_or(Self::WRITER_WAITING, Ordering::Relaxed);
+            }
+
+            hint::spin_loop();
+        }
+    }
+
+    fn release_write(&self) {
+        // Other writers may have started waiting meanwhile.
+        self.state.fetch_and(!Self::WRITER, Ordering::Release);
+    }
+
+    fn acquire_read(&self) {
+        loop {
+            let state = self.state.load(Ordering::Relaxed);
+
+            if state & (Self::WRITER | Self::WRITER_WAITING) == 0
+                && self
+                    .state
+                    .compare_exchange_weak(state, state + 1, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+            {
+                return;
+            }
+
+            hint::spin_loop();
+        }
+    }
+
+    fn release_read(&self) {
+        self.state.fetch_sub(1, Ordering::Release);
+    }
+}
+
+impl<T> Drop for IrqSafeRwSpinLock<T>
+where
+    T: ?Sized,
+{
+    fn drop(&mut self) {
+        lockdep::forget(self.addr());
+    }
+}
+
+impl<T> interface::ReadWriteEx for IrqSafeRwSpinLock<T> {
+    type Data = T;
+
+    #[cfg_attr(feature = "lockdep", track_caller)]
+    fn write<'a, R>(&'a self, f: impl FnOnce(&'a mut Self::Data) -> R) -> R {
+        let _guard = self.write_guard();
+
+        f(unsafe { &mut *self.data.get() })
+    }
+
+    #[cfg_attr(feature = "lockdep", track_caller)]
+    fn read<'a, R>(&'a self, f: impl FnOnce(&'a Self::Data) -> R) -> R {
+        let _guard = self.read_guard();
+
+        f(unsafe { &*self.data.get() })
     }
 }
 
@@ -80,27 +615,57 @@ impl<T> InitStateLock<T> {
 unsafe impl<T> Send for InitStateLock<T> where T: ?Sized + Send {}
 unsafe impl<T> Sync for InitStateLock<T> where T: ?Sized + Send {}
 
+impl<T> InitStateLock<T>
+where
+    T: ?Sized,
+{
+    /// The address the lock is validated by, see `lockdep`.
+    fn addr(&self) -> usize {
+        self as *const Self as *const () as usize
+    }
+}
+
+impl<T> Drop for InitStateLock<T>
+where
+    T: ?Sized,
+{
+    fn drop(&mut self) {
+        lockdep::forget(self.addr());
+    }
+}
+
 impl<T> interface::ReadWriteEx for InitStateLock<T> {
     type Data = T;
 
+    #[cfg_attr(feature = "lockdep", track_caller)]
     fn write<'a, R>(&'a self, f: impl FnOnce(&'a mut Self::Data) -> R) -> R {
         assert!(
             state::state_manager().is_init(),
             "InitStateLock::write called after kernel init phase"
         );
         assert!(
-            !exception::asynchronous::is_local_irq_masked(),
+            exception::asynchronous::is_local_irq_masked(),
             "InitStateLock::write called with IRQs unmasked"
         );
 
         let data = unsafe { &mut *self.data.get() };
 
-        f(data)
+        lockdep::acquire(self.addr(), Location::caller());
+        let ret = f(data);
+        lockdep::release(self.addr());
+
+        ret
     }
 
+    #[cfg_attr(feature = "lockdep", track_caller)]
     fn read<'a, R>(&'a self, f: impl FnOnce(&'a Self::Data) -> R) -> R {
         let data = unsafe { &*self.data.get() };
 
-        f(data)
+        // Reads don't mask IRQs, so they nest when an IRQ handler reads the lock too.
+        lockdep::acquire_read(self.addr(), Location::caller());
+        let ret = f(data);
+        lockdep::release(self.addr());
+
+        ret
     }
 }
@@ -0,0 +1,423 @@
+//! Lock dependency validation, with the `lockdep` feature.
+//!
+//! Every lock is a class of its own, identified by its address. Whenever a core takes a lock while
+//! holding others, the order is recorded as dependencies between their classes. Taking a lock some
+//! held lock depends on, directly or through other locks, deadlocks as soon as another core takes
+//! them the other way round, so it is reported even if it didn't hang this time. So is taking a
+//! lock the core holds already.
+//!
+//! Locks whose reads nest, even across IRQs, like `InitStateLock`, are taken for reading through
+//! `acquire_read()`. Such reads never wait for each other, so a nested read is not reported, and no
+//! dependency is recorded towards a read. Locks taken while holding one for reading still are.
+//!
+//! A report lists the locks the core holds, and the locks that were held when the conflicting
+//! order got recorded, each with where it was taken. Validation stops after the first report, which
+//! also keeps the locks taken for printing it out of the way. Reports are forced past the console's
+//! locks, since the core may hold them, see `force_println!`.
+
+use core::{cell::UnsafeCell, panic::Location};
+
+use super::TicketLock;
+use crate::{
+    cpu::smp::{self, NUM_CORES},
+    exception,
+};
+
+/// The number of locks that can be told apart.
+const MAX_CLASSES: usize = 64;
+
+/// The number of locks a core can hold at a time.
+const MAX_HELD: usize = 8;
+
+/// The number of dependencies that remember the locks held when they were recorded.
+const MAX_DEPENDENCIES: usize = 256;
+
+#[derive(Copy, Clone)]
+struct Acquisition {
+    class: usize,
+    location: &'static Location<'static>,
+    read: bool,
+}
+
+/// The locks held by a core, in the order they were taken.
+#[derive(Copy, Clone)]
+struct HeldLocks {
+    len: usize,
+    locks: [Option<Acquisition>; MAX_HELD],
+}
+
+impl HeldLocks {
+    const fn new() -> Self {
+        Self {
+            len: 0,
+            locks: [None; MAX_HELD],
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &Acquisition> {
+        self.locks[..self.len].iter().flatten()
+    }
+
+    fn push(&mut self, acquisition: Acquisition) -> Result<(), ()> {
+        if self.len == MAX_HELD {
+            return Err(());
+        }
+
+        self.locks[self.len] = Some(acquisition);
+        self.len += 1;
+
+        Ok(())
+    }
+
+    /// Locks need not be released in the order they were taken.
+    fn remove(&mut self, class: usize) {
+        let Some(index) = (0..self.len).rev().find(|i| {
+            self.locks[*i]
+                .as_ref()
+                .is_some_and(|acquisition| acquisition.class == class)
+        }) else {
+            return;
+        };
+
+        self.locks.copy_within(index + 1..self.len, index);
+        self.len -= 1;
+        self.locks[self.len] = None;
+    }
+}
+
+/// Class `to` was taken while class `from` was held, among the `held` locks.
+#[derive(Copy, Clone)]
+struct Dependency {
+    from: usize,
+    to: usize,
+    held: HeldLocks,
+}
+
+enum Report {
+    /// The core took a lock it holds already.
+    Recursive { addr: usize, held: HeldLocks },
+
+    /// The core took a lock a held lock depends on. The dependency starts at the taken lock, if
+    /// it was remembered.
+    Inversion {
+        addr: usize,
+        held: HeldLocks,
+        dependency: Option<Dependency>,
+    },
+
+    /// A fixed size table ran full.
+    OutOf(&'static str),
+}
+
+struct LockdepInner {
+    enabled: bool,
+
+    /// The address of the lock of each class, 0 for unused classes.
+    classes: [usize; MAX_CLASSES],
+
+    /// Bit `to` of `after[from]` is set if class `to` was taken while `from` was held.
+    after: [u64; MAX_CLASSES],
+
+    dependencies: [Option<Dependency>; MAX_DEPENDENCIES],
+    held: [HeldLocks; NUM_CORES],
+}
+
+impl LockdepInner {
+    const fn new() -> Self {
+        Self {
+            enabled: true,
+            classes: [0; MAX_CLASSES],
+            after: [0; MAX_CLASSES],
+            dependencies: [None; MAX_DEPENDENCIES],
+            held: [HeldLocks::new(); NUM_CORES],
+        }
+    }
+
+    fn class(&self, addr: usize) -> Option<usize> {
+        self.classes.iter().position(|x| *x == addr)
+    }
+
+    fn class_or_new(&mut self, addr: usize) -> Option<usize> {
+        if let Some(class) = self.class(addr) {
+            return Some(class);
+        }
+
+        let class = self.class(0)?;
+        self.classes[class] = addr;
+
+        Some(class)
+    }
+
+    /// The classes taken after `class`, directly or through others.
+    fn reachable_from(&self, class: usize) -> u64 {
+        let mut reached = self.after[class];
+
+        loop {
+            let next = (0..MAX_CLASSES)
+                .filter(|x| reached & (1 << x) != 0)
+                .fold(reached, |acc, x| acc | self.after[x]);
+
+            if next == reached {
+                return reached;
+            }
+            reached = next;
+        }
+    }
+
+    /// The remembered dependency on the way from `from` to `to`.
+    fn dependency_towards(&self, from: usize, to: usize) -> Option<Dependency> {
+        let next = (0..MAX_CLASSES).find(|x| {
+            self.after[from] & (1 << x) != 0
+                && (*x == to || self.reachable_from(*x) & (1 << to) != 0)
+        })?;
+
+        self.dependencies
+            .iter()
+            .flatten()
+            .find(|dependency| dependency.from == from && dependency.to == next)
+            .copied()
+    }
+
+    fn acquire(
+        &mut self,
+        addr: usize,
+        location: &'static Location<'static>,
+        read: bool,
+    ) -> Option<Report> {
+        let Some(class) = self.class_or_new(addr) else {
+            return Some(Report::OutOf("lock classes"));
+        };
+        let core = smp::core_id::<usize>();
+        let held = self.held[core];
+        let acquisition = Acquisition {
+            class,
+            location,
+            read,
+        };
+
+        if let Some(previous) = held.iter().find(|x| x.class == class) {
+            if !(read && previous.read) {
+                return Some(Report::Recursive { addr, held });
+            }
+
+            // Only the outermost read counts, this one just has to be released again.
+            return match self.held[core].push(acquisition) {
+                Ok(()) => None,
+                Err(()) => Some(Report::OutOf("held locks")),
+            };
+        }
+
+        let reachable = self.reachable_from(class);
+        if let Some(conflict) = held.iter().find(|x| reachable & (1 << x.class) != 0) {
+            return Some(Report::Inversion {
+                addr,
+                held,
+                dependency: self.dependency_towards(class, conflict.class),
+            });
+        }
+
+        if self.held[core].push(acquisition).is_err() {
+            return Some(Report::OutOf("held locks"));
+        }
+
+        if read {
+            return None;
+        }
+
+        for from in held.iter().map(|x| x.class) {
+            if self.after[from] & (1 << class) != 0 {
+                continue;
+            }
+            self.after[from] |= 1 << class;
+
+            // Without a free slot, the dependency is still checked, just reported without stack.
+            if let Some(slot) = self.dependencies.iter_mut().find(|x| x.is_none()) {
+                *slot = Some(Dependency {
+                    from,
+                    to: class,
+                    held: self.held[core],
+                });
+            }
+        }
+
+        None
+    }
+
+    fn release(&mut self, addr: usize) {
+        if let Some(class) = self.class(addr) {
+            self.held[smp::core_id::<usize>()].remove(class);
+        }
+    }
+
+    /// Drop the class of a lock that goes away, so a new lock at its address starts afresh.
+    fn forget(&mut self, addr: usize) {
+        let Some(class) = self.class(addr) else {
+            return;
+        };
+
+        self.classes[class] = 0;
+        self.after[class] = 0;
+        self.after.iter_mut().for_each(|x| *x &= !(1 << class));
+
+        for slot in self.dependencies.iter_mut() {
+            if slot.is_some_and(|x| x.from == class || x.to == class) {
+                *slot = None;
+            }
+        }
+    }
+}
+
+struct Lockdep {
+    lock: TicketLock,
+    inner: UnsafeCell<LockdepInner>,
+}
+
+unsafe impl Sync for Lockdep {}
+
+static LOCKDEP: Lockdep = Lockdep {
+    lock: TicketLock::new(),
+    inner: UnsafeCell::new(LockdepInner::new()),
+};
+
+impl Lockdep {
+    /// Run `f` on the state, unless validation stopped. Returns the report `f` made, after which
+    /// validation stops, together with the class addresses for printing it.
+    fn with_inner(
+        &self,
+        f: impl FnOnce(&mut LockdepInner) -> Option<Report>,
+    ) -> Option<(Report, [usize; MAX_CLASSES])> {
+        // The validator's own lock is not validated, and IRQ handlers take locks too.
+        exception::asynchronous::exec_with_irq_masked(|| {
+            self.lock.acquire();
+            let inner = unsafe { &mut *self.inner.get() };
+
+            let ret = match inner.enabled {
+                true => f(inner).map(|report| {
+                    inner.enabled = false;
+
+                    (report, inner.classes)
+                }),
+                false => None,
+            };
+            self.lock.release();
+
+            ret
+        })
+    }
+}
+
+/// Like `warn!`, but forced past the console's locks and without a timestamp. Reports may be
+/// raised while the console or the clock are locked on this core, even by the lock taken.
+macro_rules! report {
+    ($fmt:expr) => {
+        $crate::force_println!(concat!("[W] ", $fmt))
+    };
+    ($fmt:expr, $($arg:tt)*) => {
+        $crate::force_println!(concat!("[W] ", $fmt), $($arg)*)
+    };
+}
+
+fn print_held(held: &HeldLocks, classes: &[usize; MAX_CLASSES]) {
+    for (i, acquisition) in held.iter().enumerate() {
+        report!(
+            "        {}. lock {:#x}, taken at {}",
+            i + 1,
+            classes[acquisition.class],
+            acquisition.location
+        );
+    }
+}
+
+fn print_report(report: Report, classes: &[usize; MAX_CLASSES], location: &Location) {
+    let core = smp::core_id::<usize>();
+
+    match report {
+        Report::Recursive { addr, held } => {
+            report!(
+                "Lockdep: lock {:#x} taken again at {} on core {}, which holds:",
+                addr,
+                location,
+                core
+            );
+            print_held(&held, classes);
+        }
+        Report::Inversion {
+            addr,
+            held,
+            dependency,
+        } => {
+            report!(
+                "Lockdep: lock order inversion, lock {:#x} taken at {} on core {}, which holds:",
+                addr,
+                location,
+                core
+            );
+            print_held(&held, classes);
+
+            match dependency {
+                Some(dependency) => {
+                    report!(
+                        "Lockdep: earlier, lock {:#x} was taken while holding lock {:#x}:",
+                        classes[dependency.to],
+                        classes[dependency.from]
+                    );
+                    print_held(&dependency.held, classes);
+                }
+                None => {
+                    report!("Lockdep: the earlier order was not remembered");
+                }
+            }
+        }
+        Report::OutOf(what) => {
+            report!("Lockdep: out of {}, taken at {}", what, location);
+        }
+    }
+
+    report!("Lockdep: validation stopped");
+}
+
+/// Return whether a report was made. Validation stopped then.
+pub fn reported() -> bool {
+    exception::asynchronous::exec_with_irq_masked(|| {
+        LOCKDEP.lock.acquire();
+        let enabled = unsafe { (*LOCKDEP.inner.get()).enabled };
+        LOCKDEP.lock.release();
+
+        !enabled
+    })
+}
+
+/// Called before a lock at `addr` is taken.
+pub(super) fn acquire(addr: usize, location: &'static Location<'static>) {
+    if let Some((report, classes)) =
+        LOCKDEP.with_inner(|inner| inner.acquire(addr, location, false))
+    {
+        print_report(report, &classes, location);
+    }
+}
+
+/// Called before a lock at `addr` whose reads nest is taken for reading.
+pub(super) fn acquire_read(addr: usize, location: &'static Location<'static>) {
+    if let Some((report, classes)) = LOCKDEP.with_inner(|inner| inner.acquire(addr, location, true))
+    {
+        print_report(report, &classes, location);
+    }
+}
+
+/// Called after the lock at `addr` was released.
+pub(super) fn release(addr: usize) {
+    LOCKDEP.with_inner(|inner| {
+        inner.release(addr);
+
+        None
+    });
+}
+
+/// Called when the lock at `addr` is dropped.
+pub(super) fn forget(addr: usize) {
+    LOCKDEP.with_inner(|inner| {
+        inner.forget(addr);
+
+        None
+    });
+}
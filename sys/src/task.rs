@@ -0,0 +1,119 @@
+//! User tasks, running in EL0 in an address space of their own.
+
+#[cfg(target_arch = "aarch64")]
+#[path = "arch/aarch64/task.rs"]
+mod arch_task;
+
+pub mod elf;
+
+pub(crate) use arch_task::UserContext;
+
+use crate::{
+    exception::syscall::{syscall_table, SyscallArgs, SyscallDescriptor},
+    memory::{
+        address_space::AddressSpace,
+        mmu::{
+            self, interface::Mmu, AccessPermissions, AttributeFields, KernelGranule, MemAttributes,
+            USER_ADDR_SPACE_SIZE,
+        },
+    },
+};
+
+/// Syscall number of `exit(code)`, which ends the calling task.
+pub const SYS_EXIT: usize = 0;
+
+/// The size of a user task's stack.
+pub const USER_STACK_SIZE: usize = 64 * 1024;
+
+/// The user stack ends at the top of the user address space. Nothing is mapped below it, so an
+/// overflow faults.
+pub const USER_STACK_TOP: usize = USER_ADDR_SPACE_SIZE;
+
+/// How a user task ended.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum TaskExit {
+    /// The task called `exit` with this code.
+    Exited(u64),
+
+    /// The task caused an exception the kernel can't resolve for it.
+    Killed,
+}
+
+/// A task running in user mode.
+pub struct UserTask {
+    name: &'static str,
+    address_space: AddressSpace,
+    entry: usize,
+    stack_pointer: usize,
+}
+
+impl UserTask {
+    /// Create a task with a fresh address space that only holds its stack.
+    pub fn new(name: &'static str) -> Result<Self, &'static str> {
+        let mut address_space = AddressSpace::new()?;
+
+        let stack_attributes = AttributeFields {
+            mem_attributes: MemAttributes::CacheableDram,
+            acc_perms: AccessPermissions::UserReadWrite,
+            execute_never: true,
+        };
+        address_space.map_new_pages(
+            USER_STACK_TOP - USER_STACK_SIZE,
+            USER_STACK_SIZE.div_ceil(KernelGranule::SIZE),
+            &stack_attributes,
+        )?;
+
+        Ok(Self {
+            name,
+            address_space,
+            entry: 0,
+            stack_pointer: USER_STACK_TOP,
+        })
+    }
+
+    /// Return the name.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Return the address space, for mapping and loading the task's code and data.
+    pub fn address_space_mut(&mut self) -> &mut AddressSpace {
+        &mut self.address_space
+    }
+
+    /// Set the user address execution starts at.
+    pub fn set_entry(&mut self, entry: usize) {
+        self.entry = entry;
+    }
+
+    /// Set the initial user stack pointer. It defaults to `USER_STACK_TOP`.
+    pub fn set_stack_pointer(&mut self, stack_pointer: usize) {
+        self.stack_pointer = stack_pointer;
+    }
+
+    /// Switch to the task's address space and run it in EL0 until it exits or is killed. `arg`
+    /// is passed in x0.
+    pub fn run(&mut self, arg: u64) -> TaskExit {
+        unsafe {
+            self.address_space.activate();
+            let exit = arch_task::enter_user(self.entry, self.stack_pointer, arg);
+            mmu::mmu().deactivate_user_table();
+
+            exit
+        }
+    }
+}
+
+/// End the running user task. Called by exception handling on behalf of the task.
+pub fn exit_current_task(exit: TaskExit) -> ! {
+    arch_task::exit_to_kernel(exit)
+}
+
+fn sys_exit(args: &SyscallArgs) -> u64 {
+    exit_current_task(TaskExit::Exited(args[0]))
+}
+
+/// Register the syscalls of user tasks.
+pub fn register_syscalls() -> Result<(), &'static str> {
+    syscall_table().register_syscall(SyscallDescriptor::new(SYS_EXIT, "exit", sys_exit))
+}
@@ -0,0 +1,256 @@
+//! Loader for statically linked AArch64 ELF64 executables.
+
+use alloc::vec::Vec;
+
+use crate::{
+    memory::{
+        address_space::AddressSpace,
+        frame_alloc::FRAME_SIZE,
+        mmu::{AccessPermissions, AttributeFields, MemAttributes},
+    },
+    task::{UserTask, USER_STACK_SIZE, USER_STACK_TOP},
+};
+
+const ELF_MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+const ELFCLASS64: u8 = 2;
+const ELFDATA2LSB: u8 = 1;
+const EV_CURRENT: u8 = 1;
+const ET_EXEC: u16 = 2;
+const EM_AARCH64: u16 = 183;
+
+const ELF_HEADER_SIZE: usize = 64;
+const PROGRAM_HEADER_SIZE: usize = 56;
+
+const PT_LOAD: u32 = 1;
+
+const PF_X: u32 = 1 << 0;
+const PF_W: u32 = 1 << 1;
+
+/// Read a little endian value of `N` bytes at `offset`.
+fn read_bytes<const N: usize>(image: &[u8], offset: usize) -> Result<[u8; N], &'static str> {
+    offset
+        .checked_add(N)
+        .and_then(|end| image.get(offset..end))
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or("ELF image truncated")
+}
+
+fn read_u16(image: &[u8], offset: usize) -> Result<u16, &'static str> {
+    read_bytes(image, offset).map(u16::from_le_bytes)
+}
+
+fn read_u32(image: &[u8], offset: usize) -> Result<u32, &'static str> {
+    read_bytes(image, offset).map(u32::from_le_bytes)
+}
+
+fn read_u64(image: &[u8], offset: usize) -> Result<usize, &'static str> {
+    read_bytes(image, offset).map(|x| u64::from_le_bytes(x) as usize)
+}
+
+/// The parts of the ELF header the loader needs.
+struct ElfHeader {
+    entry: usize,
+    phoff: usize,
+    phnum: usize,
+}
+
+impl ElfHeader {
+    /// Parse and validate the header of an image.
+    fn parse(image: &[u8]) -> Result<Self, &'static str> {
+        let ident: [u8; 16] = read_bytes(image, 0)?;
+
+        if ident[0..4] != ELF_MAGIC {
+            return Err("Not an ELF image");
+        }
+
+        if ident[4] != ELFCLASS64 || ident[5] != ELFDATA2LSB || ident[6] != EV_CURRENT {
+            return Err("Not a little endian ELF64 image");
+        }
+
+        if read_u16(image, 16)? != ET_EXEC {
+            return Err("Not an ELF executable");
+        }
+
+        if read_u16(image, 18)? != EM_AARCH64 {
+            return Err("Not an AArch64 ELF image");
+        }
+
+        if read_u16(image, 52)? as usize != ELF_HEADER_SIZE
+            || read_u16(image, 54)? as usize != PROGRAM_HEADER_SIZE
+        {
+            return Err("Unexpected ELF header sizes");
+        }
+
+        Ok(Self {
+            entry: read_u64(image, 24)?,
+            phoff: read_u64(image, 32)?,
+            phnum: read_u16(image, 56)? as usize,
+        })
+    }
+}
+
+/// A loadable segment.
+struct Segment {
+    flags: u32,
+    offset: usize,
+    vaddr: usize,
+    filesz: usize,
+    memsz: usize,
+}
+
+impl Segment {
+    /// Parse the `nr`-th program header. Returns `None` for other types than `PT_LOAD`.
+    fn parse(image: &[u8], header: &ElfHeader, nr: usize) -> Result<Option<Self>, &'static str> {
+        let base = nr
+            .checked_mul(PROGRAM_HEADER_SIZE)
+            .and_then(|x| x.checked_add(header.phoff))
+            .ok_or("ELF image truncated")?;
+
+        if read_u32(image, base)? != PT_LOAD {
+            return Ok(None);
+        }
+
+        let segment = Self {
+            flags: read_u32(image, base + 4)?,
+            offset: read_u64(image, base + 8)?,
+            vaddr: read_u64(image, base + 16)?,
+            filesz: read_u64(image, base + 32)?,
+            memsz: read_u64(image, base + 40)?,
+        };
+
+        if segment.filesz > segment.memsz {
+            return Err("Segment file size exceeds its memory size");
+        }
+
+        if segment
+            .offset
+            .checked_add(segment.filesz)
+            .is_none_or(|end| end > image.len())
+        {
+            return Err("ELF image truncated");
+        }
+
+        Ok(Some(segment))
+    }
+
+    /// The attributes the segment is mapped with, derived from its flags.
+    fn attribute_fields(&self) -> Result<AttributeFields, &'static str> {
+        let writable = self.flags & PF_W != 0;
+        let executable = self.flags & PF_X != 0;
+
+        if writable && executable {
+            return Err("Writable and executable segment");
+        }
+
+        Ok(AttributeFields {
+            mem_attributes: MemAttributes::CacheableDram,
+            acc_perms: match writable {
+                true => AccessPermissions::UserReadWrite,
+                false => AccessPermissions::UserReadOnly,
+            },
+            execute_never: !executable,
+        })
+    }
+
+    /// Map the pages the segment covers and copy its contents there. Pages come zeroed, which
+    /// takes care of the part beyond the file size, aka BSS.
+    fn load(&self, image: &[u8], address_space: &mut AddressSpace) -> Result<(), &'static str> {
+        if self.memsz == 0 {
+            return Ok(());
+        }
+
+        let start = self.vaddr & !(FRAME_SIZE - 1);
+        let end_exclusive = self
+            .vaddr
+            .checked_add(self.memsz)
+            .and_then(|x| x.checked_next_multiple_of(FRAME_SIZE))
+            .ok_or("Segment address range overflows")?;
+
+        if end_exclusive > USER_STACK_TOP - USER_STACK_SIZE {
+            return Err("Segment overlaps the user stack");
+        }
+
+        // Segments sharing a page would need the attributes of both.
+        if (start..end_exclusive)
+            .step_by(FRAME_SIZE)
+            .any(|page| address_space.translate(page).is_some())
+        {
+            return Err("Segments share a page");
+        }
+
+        address_space.map_new_pages(
+            start,
+            (end_exclusive - start) / FRAME_SIZE,
+            &self.attribute_fields()?,
+        )?;
+
+        address_space.copy_to_user(self.vaddr, &image[self.offset..self.offset + self.filesz])
+    }
+}
+
+/// Build the initial stack below `USER_STACK_TOP`: the argument strings at the top, then, at the
+/// returned stack pointer, argc, the argv pointers, and empty envp and auxv vectors.
+fn push_args(address_space: &mut AddressSpace, args: &[&str]) -> Result<usize, &'static str> {
+    let strings_size: usize = args.iter().map(|arg| arg.len() + 1).sum();
+    let strings_start = USER_STACK_TOP
+        .checked_sub(strings_size)
+        .ok_or("Arguments exceed the user stack")?;
+
+    // argc, argv with its terminating null, the null of envp, and the AT_NULL entry of auxv.
+    let num_words = 1 + args.len() + 1 + 1 + 2;
+    let stack_pointer = (strings_start & !0xf)
+        .checked_sub(num_words * 8)
+        .map(|x| x & !0xf)
+        .ok_or("Arguments exceed the user stack")?;
+
+    if stack_pointer < USER_STACK_TOP - USER_STACK_SIZE {
+        return Err("Arguments exceed the user stack");
+    }
+
+    let mut strings = Vec::with_capacity(strings_size);
+    let mut words = Vec::with_capacity(num_words);
+    words.push(args.len() as u64);
+
+    for arg in args {
+        words.push((strings_start + strings.len()) as u64);
+        strings.extend_from_slice(arg.as_bytes());
+        strings.push(0);
+    }
+    words.extend_from_slice(&[0, 0, 0, 0]);
+
+    let words: Vec<u8> = words.iter().flat_map(|x| x.to_le_bytes()).collect();
+
+    address_space.copy_to_user(strings_start, &strings)?;
+    address_space.copy_to_user(stack_pointer, &words)?;
+
+    Ok(stack_pointer)
+}
+
+/// Create a user task running a statically linked ELF64 executable. `args` end up on its stack,
+/// which is laid out as the AArch64 Linux ABI describes it.
+pub fn load(name: &'static str, image: &[u8], args: &[&str]) -> Result<UserTask, &'static str> {
+    let header = ElfHeader::parse(image)?;
+    let mut user_task = UserTask::new(name)?;
+
+    let mut entry_executable = false;
+
+    for nr in 0..header.phnum {
+        if let Some(segment) = Segment::parse(image, &header, nr)? {
+            segment.load(image, user_task.address_space_mut())?;
+
+            entry_executable |= segment.flags & PF_X != 0
+                && (segment.vaddr..segment.vaddr + segment.memsz).contains(&header.entry);
+        }
+    }
+
+    if !entry_executable {
+        return Err("Entry point outside the executable segments");
+    }
+
+    let stack_pointer = push_args(user_task.address_space_mut(), args)?;
+
+    user_task.set_entry(header.entry);
+    user_task.set_stack_pointer(stack_pointer);
+
+    Ok(user_task)
+}
@@ -1,18 +1,248 @@
 //! Timer primitives.
 
-use core::time::Duration;
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
+use core::{
+    fmt,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use crate::{
+    cpu, driver_manager, exception,
+    synchronization::{
+        interface::{Mutex, ReadWriteEx},
+        InitStateLock, IrqSafeNullLock,
+    },
+};
 
 #[cfg(target_arch = "aarch64")]
 #[path = "arch/aarch64/timer.rs"]
 mod arch_timer;
 
+pub mod datetime;
+
+use datetime::DateTime;
+
+/// Timer interfaces.
+pub mod interface {
+    use core::time::Duration;
+
+    /// A clock that can raise an interrupt at a deadline. Timeouts run on it.
+    pub trait Clock {
+        /// A descriptive name.
+        fn name(&self) -> &'static str;
+
+        /// The time since the clock started counting.
+        fn uptime(&self) -> Duration;
+
+        /// Let the clock's interrupt fire once `uptime()` reaches `deadline`. Deadlines in the
+        /// past fire right away.
+        fn set_deadline(&self, deadline: Duration);
+
+        /// Stop the clock's interrupt.
+        fn clear_deadline(&self);
+    }
+}
+
+/// The architectural timer as a clock.
+struct ArchTimerClock;
+
+impl interface::Clock for ArchTimerClock {
+    fn name(&self) -> &'static str {
+        TimerManager::COMPATIBLE
+    }
+
+    fn uptime(&self) -> Duration {
+        arch_timer::uptime()
+    }
+
+    fn set_deadline(&self, deadline: Duration) {
+        arch_timer::set_timer_irq(deadline);
+    }
+
+    fn clear_deadline(&self) {
+        arch_timer::clear_timer_irq();
+    }
+}
+
+static ARCH_TIMER_CLOCK: ArchTimerClock = ArchTimerClock;
+
+/// Below this, `sleep_for()` spins. Waking up from the timer interrupt takes about as long.
+const SLEEP_MIN_DURATION: Duration = Duration::from_micros(10);
+
+/// Identifies a timeout set with `set_timeout()` or `set_timeout_periodic()`.
+pub type TimeoutId = usize;
+
+/// Called from the timer IRQ handler when a timeout expires.
+type TimeoutCallback = Box<dyn FnMut() + Send>;
+
+struct Timeout {
+    id: TimeoutId,
+
+    /// The clock's uptime the timeout expires at.
+    deadline: Duration,
+
+    /// `None` for one-shot timeouts.
+    period: Option<Duration>,
+
+    callback: TimeoutCallback,
+}
+
+struct TimerManagerInner {
+    /// Sorted by deadline, the nearest one first.
+    timeouts: Vec<Timeout>,
+    next_id: TimeoutId,
+
+    /// The timeout whose callback is running. Reset if it is cancelled meanwhile.
+    running: Option<TimeoutId>,
+}
+
+impl TimerManagerInner {
+    pub const fn new() -> Self {
+        Self {
+            timeouts: Vec::new(),
+            next_id: 0,
+            running: None,
+        }
+    }
+
+    fn insert(&mut self, timeout: Timeout) {
+        // Behind the timeouts with the same deadline, so they expire in the order they were set.
+        let index = self
+            .timeouts
+            .partition_point(|x| x.deadline <= timeout.deadline);
+
+        self.timeouts.insert(index, timeout);
+    }
+
+    fn add(
+        &mut self,
+        clock: &dyn interface::Clock,
+        deadline: Duration,
+        period: Option<Duration>,
+        callback: TimeoutCallback,
+    ) -> TimeoutId {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        self.insert(Timeout {
+            id,
+            deadline,
+            period,
+            callback,
+        });
+        self.program(clock);
+
+        id
+    }
+
+    fn cancel(&mut self, clock: &dyn interface::Clock, id: TimeoutId) -> Result<(), &'static str> {
+        if self.running == Some(id) {
+            self.running = None;
+
+            return Ok(());
+        }
+
+        let index = self
+            .timeouts
+            .iter()
+            .position(|x| x.id == id)
+            .ok_or("No such timeout")?;
+        self.timeouts.remove(index);
+        self.program(clock);
+
+        Ok(())
+    }
+
+    /// Take out the nearest timeout if it expired by `now`.
+    fn pop_expired(&mut self, now: Duration) -> Option<Timeout> {
+        if self.timeouts.first()?.deadline > now {
+            return None;
+        }
+
+        let timeout = self.timeouts.remove(0);
+        self.running = Some(timeout.id);
+
+        Some(timeout)
+    }
+
+    /// Put back a periodic timeout after its callback ran, unless it was cancelled meanwhile.
+    fn finish(&mut self, mut timeout: Timeout, now: Duration) {
+        if self.running.take() != Some(timeout.id) {
+            return;
+        }
+
+        if let Some(period) = timeout.period {
+            // Skip the periods that were missed instead of firing for each of them.
+            timeout.deadline += period;
+            if timeout.deadline <= now {
+                timeout.deadline = now + period;
+            }
+
+            self.insert(timeout);
+        }
+    }
+
+    /// Let the clock's interrupt fire for the nearest deadline.
+    fn program(&self, clock: &dyn interface::Clock) {
+        match self.timeouts.first() {
+            Some(timeout) => clock.set_deadline(timeout.deadline),
+            None => clock.clear_deadline(),
+        }
+    }
+}
+
 /// Provides time management functions.
-pub struct TimerManager;
+pub struct TimerManager {
+    inner: IrqSafeNullLock<TimerManagerInner>,
+
+    /// The clock timeouts run on. Writable only during kernel init.
+    clock: InitStateLock<&'static (dyn interface::Clock + Sync)>,
+
+    /// Set once the timer interrupt is handled, which timeouts depend on.
+    irq_enabled: AtomicBool,
+
+    /// The time since the Unix epoch at zero uptime. `None` until the wall clock is set.
+    realtime_offset: IrqSafeNullLock<Option<Duration>>,
+
+    /// Whether log lines show the wall clock time next to the uptime.
+    log_utc: AtomicBool,
+}
+
+/// The timestamp of log lines, see `TimerManager::log_timestamp()`.
+pub struct LogTimestamp {
+    uptime: Duration,
+    utc: Option<DateTime>,
+}
+
+impl fmt::Display for LogTimestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:>3}.{:06}",
+            self.uptime.as_secs(),
+            self.uptime.subsec_micros()
+        )?;
+
+        match &self.utc {
+            Some(utc) => write!(f, " {}", utc),
+            None => Ok(()),
+        }
+    }
+}
 
 impl TimerManager {
+    pub const COMPATIBLE: &'static str = "ARM Architectural Timer";
+
     /// Create an instance.
     pub const fn new() -> Self {
-        Self
+        Self {
+            inner: IrqSafeNullLock::new(TimerManagerInner::new()),
+            clock: InitStateLock::new(&ARCH_TIMER_CLOCK),
+            irq_enabled: AtomicBool::new(false),
+            realtime_offset: IrqSafeNullLock::new(None),
+            log_utc: AtomicBool::new(false),
+        }
     }
 
     /// The timer's resolution.
@@ -25,10 +255,213 @@ impl TimerManager {
         arch_timer::uptime()
     }
 
-    /// Spin for a given duration.
+    /// Set the wall clock to `since_epoch`, the time since the Unix epoch. Meant for a console
+    /// command, a boot argument or an RTC driver.
+    pub fn set_realtime(&self, since_epoch: Duration) -> Result<(), &'static str> {
+        let offset = since_epoch
+            .checked_sub(self.uptime())
+            .ok_or("Wall clock time before the uptime started")?;
+
+        self.realtime_offset.lock(|x| *x = Some(offset));
+
+        Ok(())
+    }
+
+    /// Set the wall clock to a date and time.
+    pub fn set_now(&self, now: &DateTime) -> Result<(), &'static str> {
+        self.set_realtime(now.to_unix())
+    }
+
+    /// The time since the Unix epoch. `None` until the wall clock is set.
+    pub fn realtime(&self) -> Option<Duration> {
+        let offset = self.realtime_offset.lock(|x| *x)?;
+
+        offset.checked_add(self.uptime())
+    }
+
+    /// The current date and time in UTC. `None` until the wall clock is set.
+    pub fn now(&self) -> Option<DateTime> {
+        DateTime::from_unix(self.realtime()?).ok()
+    }
+
+    /// Let log lines show the wall clock time in UTC next to the uptime, once it is set.
+    pub fn set_log_utc(&self, enable: bool) {
+        self.log_utc.store(enable, Ordering::Relaxed);
+    }
+
+    /// The timestamp `info!` and `warn!` put in front of log lines.
+    pub fn log_timestamp(&self) -> LogTimestamp {
+        LogTimestamp {
+            uptime: self.uptime(),
+            utc: match self.log_utc.load(Ordering::Relaxed) {
+                true => self.now(),
+                false => None,
+            },
+        }
+    }
+
+    /// The clock timeouts run on.
+    pub fn clock(&self) -> &'static (dyn interface::Clock + Sync) {
+        self.clock.read(|clock| *clock)
+    }
+
+    /// Run timeouts on another clock than the architectural timer, with its interrupt calling
+    /// `handle_timeouts()`. Pending timeouts move over.
+    pub fn register_clock(&self, new_clock: &'static (dyn interface::Clock + Sync)) {
+        self.inner.lock(|inner| {
+            let old_clock = self.clock();
+            old_clock.clear_deadline();
+
+            let (old_now, new_now) = (old_clock.uptime(), new_clock.uptime());
+            for timeout in inner.timeouts.iter_mut() {
+                timeout.deadline = timeout.deadline.saturating_sub(old_now) + new_now;
+            }
+
+            self.clock.write(|clock| *clock = new_clock);
+            inner.program(new_clock);
+        });
+    }
+
+    /// Spin for a given duration. Meant for the short delays of drivers, use `sleep_for()` for
+    /// anything longer.
     pub fn spin_for(&self, duration: Duration) {
         arch_timer::spin_for(duration);
     }
+
+    /// Wait for a given duration in a low-power state until the timer interrupt fires. Spins for
+    /// very short durations, and while the timer interrupt can't be taken.
+    pub fn sleep_for(&self, duration: Duration) {
+        if duration < SLEEP_MIN_DURATION
+            || !self.irq_enabled.load(Ordering::Relaxed)
+            || exception::asynchronous::is_local_irq_masked()
+        {
+            self.spin_for(duration);
+            return;
+        }
+
+        let expired = Arc::new(AtomicBool::new(false));
+        let expired_clone = expired.clone();
+
+        if let Err(x) = self.set_timeout(duration, move || {
+            expired_clone.store(true, Ordering::Relaxed)
+        }) {
+            panic!("sleep_for: {}", x);
+        }
+
+        // Check with IRQs masked, so the timer interrupt can't sneak in between the check and
+        // `wfi`. A pending interrupt still wakes the core up, and is taken once unmasked.
+        loop {
+            let saved = exception::asynchronous::local_irq_mask_save();
+            if expired.load(Ordering::Relaxed) {
+                exception::asynchronous::local_irq_restore(saved);
+                break;
+            }
+
+            cpu::wfi();
+            exception::asynchronous::local_irq_restore(saved);
+        }
+    }
+
+    /// Call `callback` once `duration` from now. It runs in IRQ context.
+    pub fn set_timeout(
+        &self,
+        duration: Duration,
+        callback: impl FnOnce() + Send + 'static,
+    ) -> Result<TimeoutId, &'static str> {
+        let clock = self.clock();
+        let deadline = clock
+            .uptime()
+            .checked_add(duration)
+            .ok_or("Timeout too far in the future")?;
+
+        let mut callback = Some(callback);
+        let callback = Box::new(move || {
+            if let Some(f) = callback.take() {
+                f()
+            }
+        });
+
+        Ok(self
+            .inner
+            .lock(|inner| inner.add(clock, deadline, None, callback)))
+    }
+
+    /// Call `callback` every `period`, starting `period` from now, until the timeout is cancelled.
+    /// It runs in IRQ context.
+    pub fn set_timeout_periodic(
+        &self,
+        period: Duration,
+        callback: impl FnMut() + Send + 'static,
+    ) -> Result<TimeoutId, &'static str> {
+        if period.is_zero() {
+            return Err("Period must not be zero");
+        }
+
+        let clock = self.clock();
+        let deadline = clock
+            .uptime()
+            .checked_add(period)
+            .ok_or("Timeout too far in the future")?;
+
+        Ok(self
+            .inner
+            .lock(|inner| inner.add(clock, deadline, Some(period), Box::new(callback))))
+    }
+
+    /// Cancel a timeout. A callback that is already running completes.
+    pub fn cancel_timeout(&self, id: TimeoutId) -> Result<(), &'static str> {
+        self.inner.lock(|inner| inner.cancel(self.clock(), id))
+    }
+
+    /// Run the callbacks of expired timeouts. Called by the interrupt handler of the clock.
+    pub fn handle_timeouts(&self) {
+        let clock = self.clock();
+
+        // The lock is not held while calling back, so callbacks may set and cancel timeouts.
+        loop {
+            let now = clock.uptime();
+            let Some(mut timeout) = self.inner.lock(|inner| inner.pop_expired(now)) else {
+                break;
+            };
+
+            (timeout.callback)();
+
+            self.inner.lock(|inner| inner.finish(timeout, now));
+        }
+
+        self.inner.lock(|inner| inner.program(clock));
+    }
+}
+
+impl driver_manager::interface::DeviceDriver for TimerManager {
+    type IrqNumberType = exception::asynchronous::IrqNumber;
+
+    fn compatible(&self) -> &'static str {
+        Self::COMPATIBLE
+    }
+
+    fn register_and_enable_irq_handler(
+        &'static self,
+        irq_number: &Self::IrqNumberType,
+    ) -> Result<(), &'static str> {
+        use exception::asynchronous::{irq_manager, IrqHandlerDescriptor};
+
+        let descriptor = IrqHandlerDescriptor::new(*irq_number, Self::COMPATIBLE, self);
+
+        irq_manager().register_handler(descriptor)?;
+        irq_manager().enable(irq_number);
+        self.irq_enabled.store(true, Ordering::Relaxed);
+
+        Ok(())
+    }
+}
+
+impl exception::asynchronous::interface::IrqHandler for TimerManager {
+    fn handle(&self) -> Result<(), &'static str> {
+        self.handle_timeouts();
+
+        Ok(())
+    }
 }
 
 static TIMER_MANAGER: TimerManager = TimerManager::new();
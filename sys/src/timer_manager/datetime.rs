@@ -0,0 +1,204 @@
+//! Calendar dates and times in UTC, for the wall clock.
+
+use core::{fmt, time::Duration};
+
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Days from 1970-01-01 to 0000-03-01 of the proleptic Gregorian calendar, which starts the
+/// 400 year eras the conversions count in.
+const DAYS_FROM_ERA_START_TO_EPOCH: u64 = 719_468;
+
+const DAYS_PER_ERA: u64 = 146_097;
+
+/// A point in time in UTC, from 1970 up to the year 9999.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct DateTime {
+    year: u32,
+    month: u8,
+    day: u8,
+    hour: u8,
+    minute: u8,
+    second: u8,
+    nanos: u32,
+}
+
+fn is_leap_year(year: u32) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+fn days_in_month(year: u32, month: u8) -> u8 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Parse the decimal number in `s[range]`.
+fn parse_number(s: &[u8], range: core::ops::Range<usize>) -> Result<u32, &'static str> {
+    s.get(range)
+        .filter(|digits| digits.iter().all(u8::is_ascii_digit))
+        .map(|digits| {
+            digits
+                .iter()
+                .fold(0, |acc, digit| acc * 10 + (digit - b'0') as u32)
+        })
+        .ok_or("Malformed date and time")
+}
+
+impl DateTime {
+    /// The first year that can be represented.
+    pub const MIN_YEAR: u32 = 1970;
+
+    /// The last year that can be represented.
+    pub const MAX_YEAR: u32 = 9999;
+
+    /// Create an instance, checking that the fields make up a valid date and time.
+    pub fn new(
+        year: u32,
+        month: u8,
+        day: u8,
+        hour: u8,
+        minute: u8,
+        second: u8,
+    ) -> Result<Self, &'static str> {
+        if !(Self::MIN_YEAR..=Self::MAX_YEAR).contains(&year) {
+            return Err("Year out of range");
+        }
+
+        if !(1..=12).contains(&month) || !(1..=days_in_month(year, month)).contains(&day) {
+            return Err("Invalid date");
+        }
+
+        if hour > 23 || minute > 59 || second > 59 {
+            return Err("Invalid time of day");
+        }
+
+        Ok(Self {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+            nanos: 0,
+        })
+    }
+
+    /// Parse an ISO 8601 date and time in UTC like "2025-12-11T08:30:00Z". The 'T' may be a
+    /// space and the 'Z' may be left out.
+    pub fn parse(s: &str) -> Result<Self, &'static str> {
+        let s = s.strip_suffix('Z').unwrap_or(s).as_bytes();
+
+        if s.len() != 19
+            || s[4] != b'-'
+            || s[7] != b'-'
+            || (s[10] != b'T' && s[10] != b' ')
+            || s[13] != b':'
+            || s[16] != b':'
+        {
+            return Err("Malformed date and time");
+        }
+
+        Self::new(
+            parse_number(s, 0..4)?,
+            parse_number(s, 5..7)? as u8,
+            parse_number(s, 8..10)? as u8,
+            parse_number(s, 11..13)? as u8,
+            parse_number(s, 14..16)? as u8,
+            parse_number(s, 17..19)? as u8,
+        )
+    }
+
+    /// Convert the time since the Unix epoch, 1970-01-01T00:00:00Z.
+    pub fn from_unix(since_epoch: Duration) -> Result<Self, &'static str> {
+        let secs = since_epoch.as_secs();
+        let secs_of_day = secs % SECS_PER_DAY;
+
+        // See "chrono-Compatible Low-Level Date Algorithms" by Howard Hinnant.
+        let days = secs / SECS_PER_DAY + DAYS_FROM_ERA_START_TO_EPOCH;
+        let era = days / DAYS_PER_ERA;
+        let day_of_era = days % DAYS_PER_ERA;
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month_from_march = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+        let month = match month_from_march {
+            0..=9 => month_from_march + 3,
+            _ => month_from_march - 9,
+        };
+        let year = year_of_era + era * 400 + (month <= 2) as u64;
+
+        if year > Self::MAX_YEAR as u64 {
+            return Err("Year out of range");
+        }
+
+        Ok(Self {
+            year: year as u32,
+            month: month as u8,
+            day: day as u8,
+            hour: (secs_of_day / 3600) as u8,
+            minute: (secs_of_day / 60 % 60) as u8,
+            second: (secs_of_day % 60) as u8,
+            nanos: since_epoch.subsec_nanos(),
+        })
+    }
+
+    /// Return the time since the Unix epoch.
+    pub fn to_unix(&self) -> Duration {
+        let month = self.month as u64;
+        let year = self.year as u64 - (month <= 2) as u64;
+        let era = year / 400;
+        let year_of_era = year % 400;
+        let month_from_march = match month {
+            3..=12 => month - 3,
+            _ => month + 9,
+        };
+        let day_of_year = (153 * month_from_march + 2) / 5 + self.day as u64 - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        let days = era * DAYS_PER_ERA + day_of_era - DAYS_FROM_ERA_START_TO_EPOCH;
+
+        let secs = days * SECS_PER_DAY
+            + self.hour as u64 * 3600
+            + self.minute as u64 * 60
+            + self.second as u64;
+
+        Duration::new(secs, self.nanos)
+    }
+
+    pub fn year(&self) -> u32 {
+        self.year
+    }
+
+    pub fn month(&self) -> u8 {
+        self.month
+    }
+
+    pub fn day(&self) -> u8 {
+        self.day
+    }
+
+    pub fn hour(&self) -> u8 {
+        self.hour
+    }
+
+    pub fn minute(&self) -> u8 {
+        self.minute
+    }
+
+    pub fn second(&self) -> u8 {
+        self.second
+    }
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}